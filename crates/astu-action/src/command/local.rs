@@ -0,0 +1,211 @@
+use std::ffi::OsStr;
+use std::path::Path;
+use std::process;
+
+use astu_types::Target;
+use astu_types::TargetKind;
+use eyre::Result;
+use eyre::bail;
+use tokio::process::Command;
+
+use crate::command;
+use crate::command::Stdio;
+
+/// Factory that runs commands as processes on the current machine.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LocalCommandFactory;
+
+impl command::CommandFactory for LocalCommandFactory {
+    type Command = LocalCommand;
+
+    async fn command(&self, target: &Target, program: &OsStr) -> Result<Self::Command> {
+        if target.kind() != TargetKind::Local {
+            bail!("unsupported target: {target}");
+        }
+        Ok(LocalCommand::new(program))
+    }
+}
+
+#[derive(Debug)]
+pub struct LocalCommand {
+    inner: Command,
+}
+
+impl LocalCommand {
+    /// Commands run in their own process group, so that a Ctrl-C in the
+    /// terminal stops only astu, which then decides whether to wait for them.
+    #[must_use]
+    pub fn new(program: impl AsRef<OsStr>) -> Self {
+        let mut inner = Command::new(program);
+        inner
+            .stdin(process::Stdio::null())
+            .stdout(process::Stdio::piped())
+            .stderr(process::Stdio::piped())
+            .process_group(0)
+            .kill_on_drop(true);
+        Self { inner }
+    }
+}
+
+impl command::Command for LocalCommand {
+    type Child = LocalChild;
+
+    fn arg(&mut self, arg: impl AsRef<OsStr>) -> &mut Self {
+        self.inner.arg(arg);
+        self
+    }
+
+    fn args<I, S>(&mut self, args: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.inner.args(args);
+        self
+    }
+
+    fn env(&mut self, key: impl AsRef<OsStr>, val: impl AsRef<OsStr>) -> &mut Self {
+        self.inner.env(key, val);
+        self
+    }
+
+    fn envs<I, K, V>(&mut self, vars: I) -> &mut Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>,
+    {
+        self.inner.envs(vars);
+        self
+    }
+
+    fn env_remove(&mut self, key: impl AsRef<OsStr>) -> &mut Self {
+        self.inner.env_remove(key);
+        self
+    }
+
+    fn env_clear(&mut self) -> &mut Self {
+        self.inner.env_clear();
+        self
+    }
+
+    fn current_dir(&mut self, dir: impl AsRef<Path>) -> &mut Self {
+        self.inner.current_dir(dir);
+        self
+    }
+
+    fn stdin(&mut self, cfg: Stdio) -> &mut Self {
+        self.inner.stdin(cfg);
+        self
+    }
+
+    fn stdout(&mut self, cfg: Stdio) -> &mut Self {
+        self.inner.stdout(cfg);
+        self
+    }
+
+    fn stderr(&mut self, cfg: Stdio) -> &mut Self {
+        self.inner.stderr(cfg);
+        self
+    }
+
+    fn get_program(&self) -> &OsStr {
+        self.inner.as_std().get_program()
+    }
+
+    fn get_args(&self) -> impl Iterator<Item = &OsStr> {
+        self.inner.as_std().get_args()
+    }
+
+    fn get_envs(&self) -> impl Iterator<Item = (&OsStr, Option<&OsStr>)> {
+        self.inner.as_std().get_envs()
+    }
+
+    fn get_current_dir(&self) -> Option<&Path> {
+        self.inner.as_std().get_current_dir()
+    }
+
+    async fn spawn(&mut self) -> Result<Self::Child> {
+        let child = self.inner.spawn()?;
+        Ok(LocalChild { inner: child })
+    }
+}
+
+impl From<Stdio> for process::Stdio {
+    fn from(value: Stdio) -> Self {
+        match value {
+            Stdio::Piped => Self::piped(),
+            Stdio::Null => Self::null(),
+            Stdio::Inherit => Self::inherit(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct LocalChild {
+    inner: tokio::process::Child,
+}
+
+impl command::Child for LocalChild {
+    type Stdin = tokio::process::ChildStdin;
+    type Stdout = tokio::process::ChildStdout;
+    type Stderr = tokio::process::ChildStderr;
+
+    fn take_stdin(&mut self) -> Option<Self::Stdin> {
+        self.inner.stdin.take()
+    }
+
+    fn take_stdout(&mut self) -> Option<Self::Stdout> {
+        self.inner.stdout.take()
+    }
+
+    fn take_stderr(&mut self) -> Option<Self::Stderr> {
+        self.inner.stderr.take()
+    }
+
+    async fn wait(mut self) -> Result<process::ExitStatus> {
+        Ok(self.inner.wait().await?)
+    }
+
+    async fn wait_with_output(self) -> Result<process::Output> {
+        Ok(self.inner.wait_with_output().await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rstest::rstest;
+
+    use super::*;
+    use crate::command::Command as _;
+    use crate::command::CommandFactory;
+
+    #[tokio::test]
+    async fn output_works() -> Result<()> {
+        let target = Target::from_str("local:")?;
+        let mut cmd = LocalCommandFactory
+            .command(&target, OsStr::new("sh"))
+            .await?;
+        cmd.args(["-c", "echo out; echo err >&2; exit 3"]);
+
+        let output = cmd.output().await?;
+
+        assert_eq!(output.stdout, b"out\n");
+        assert_eq!(output.stderr, b"err\n");
+        assert_eq!(output.status.code(), Some(3));
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("ip://127.0.0.1")]
+    #[case("ssh://localhost")]
+    #[tokio::test]
+    async fn command_rejects_remote(#[case] target: &str) -> Result<()> {
+        let target = Target::from_str(target)?;
+        let result = LocalCommandFactory.command(&target, OsStr::new("sh")).await;
+        assert!(result.is_err());
+        Ok(())
+    }
+}
@@ -1 +1,373 @@
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::ffi::OsString;
+use std::io::Cursor;
+use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::process;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Duration;
 
+use astu_types::Target;
+use eyre::Result;
+use eyre::bail;
+use eyre::eyre;
+use tokio::io::AsyncWrite;
+
+use crate::command;
+use crate::command::Stdio;
+
+/// Scripted behavior of a mock command on a single target.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MockScript {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub exitcode: i32,
+    /// How long the command runs before exiting.
+    pub delay: Duration,
+    /// If set, spawning the command fails with this message.
+    pub spawn_error: Option<String>,
+    /// If set, the command never exits.
+    pub hang: bool,
+}
+
+/// A command spawned by a [`MockCommandFactory`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockInvocation {
+    pub target: Target,
+    pub program: OsString,
+    pub args: Vec<OsString>,
+    /// Everything written to stdin so far.
+    pub stdin: Vec<u8>,
+}
+
+/// Factory for commands whose behavior is scripted per target.
+///
+/// Targets without a script of their own use the default script, which exits
+/// successfully without any output.
+#[derive(Debug, Clone, Default)]
+pub struct MockCommandFactory {
+    scripts: BTreeMap<Target, MockScript>,
+    default: MockScript,
+    invocations: Arc<Mutex<Vec<MockInvocation>>>,
+}
+
+impl MockCommandFactory {
+    /// Scripts the behavior for a target.
+    #[must_use]
+    pub fn with(mut self, target: Target, script: MockScript) -> Self {
+        self.scripts.insert(target, script);
+        self
+    }
+
+    /// Scripts the behavior for targets without a script of their own.
+    #[must_use]
+    pub fn with_default(mut self, script: MockScript) -> Self {
+        self.default = script;
+        self
+    }
+
+    /// Commands spawned so far, in order.
+    #[must_use]
+    pub fn invocations(&self) -> Vec<MockInvocation> {
+        self.invocations
+            .lock()
+            .map(|x| x.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl command::CommandFactory for MockCommandFactory {
+    type Command = MockCommand;
+
+    async fn command(&self, target: &Target, program: &OsStr) -> Result<Self::Command> {
+        let script = self.scripts.get(target).unwrap_or(&self.default).clone();
+        Ok(MockCommand {
+            target: target.clone(),
+            script,
+            invocations: self.invocations.clone(),
+            inner: process::Command::new(program),
+            stdin: Stdio::Null,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct MockCommand {
+    target: Target,
+    script: MockScript,
+    invocations: Arc<Mutex<Vec<MockInvocation>>>,
+    inner: process::Command,
+    stdin: Stdio,
+}
+
+impl command::Command for MockCommand {
+    type Child = MockChild;
+
+    fn arg(&mut self, arg: impl AsRef<OsStr>) -> &mut Self {
+        self.inner.arg(arg);
+        self
+    }
+
+    fn args<I, S>(&mut self, args: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.inner.args(args);
+        self
+    }
+
+    fn env(&mut self, key: impl AsRef<OsStr>, val: impl AsRef<OsStr>) -> &mut Self {
+        self.inner.env(key, val);
+        self
+    }
+
+    fn envs<I, K, V>(&mut self, vars: I) -> &mut Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>,
+    {
+        self.inner.envs(vars);
+        self
+    }
+
+    fn env_remove(&mut self, key: impl AsRef<OsStr>) -> &mut Self {
+        self.inner.env_remove(key);
+        self
+    }
+
+    fn env_clear(&mut self) -> &mut Self {
+        self.inner.env_clear();
+        self
+    }
+
+    fn current_dir(&mut self, dir: impl AsRef<Path>) -> &mut Self {
+        self.inner.current_dir(PathBuf::from(dir.as_ref()));
+        self
+    }
+
+    fn stdin(&mut self, cfg: Stdio) -> &mut Self {
+        self.stdin = cfg;
+        self
+    }
+
+    fn stdout(&mut self, _cfg: Stdio) -> &mut Self {
+        self
+    }
+
+    fn stderr(&mut self, _cfg: Stdio) -> &mut Self {
+        self
+    }
+
+    fn get_program(&self) -> &OsStr {
+        self.inner.get_program()
+    }
+
+    fn get_args(&self) -> impl Iterator<Item = &OsStr> {
+        self.inner.get_args()
+    }
+
+    fn get_envs(&self) -> impl Iterator<Item = (&OsStr, Option<&OsStr>)> {
+        self.inner.get_envs()
+    }
+
+    fn get_current_dir(&self) -> Option<&Path> {
+        self.inner.get_current_dir()
+    }
+
+    async fn spawn(&mut self) -> Result<Self::Child> {
+        if let Some(error) = &self.script.spawn_error {
+            bail!("{error}");
+        }
+        let invocation = MockInvocation {
+            target: self.target.clone(),
+            program: self.inner.get_program().to_owned(),
+            args: self.inner.get_args().map(ToOwned::to_owned).collect(),
+            stdin: Vec::new(),
+        };
+        let index = {
+            let mut invocations = self
+                .invocations
+                .lock()
+                .map_err(|_| eyre!("mock invocations lock poisoned"))?;
+            invocations.push(invocation);
+            invocations.len() - 1
+        };
+        let stdin = (self.stdin == Stdio::Piped).then(|| MockStdin {
+            invocations: self.invocations.clone(),
+            index,
+        });
+        Ok(MockChild {
+            stdin,
+            stdout: Some(Cursor::new(self.script.stdout.clone())),
+            stderr: Some(Cursor::new(self.script.stderr.clone())),
+            script: self.script.clone(),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct MockChild {
+    script: MockScript,
+    stdin: Option<MockStdin>,
+    stdout: Option<Cursor<Vec<u8>>>,
+    stderr: Option<Cursor<Vec<u8>>>,
+}
+
+impl command::Child for MockChild {
+    type Stdin = MockStdin;
+    type Stdout = Cursor<Vec<u8>>;
+    type Stderr = Cursor<Vec<u8>>;
+
+    fn take_stdin(&mut self) -> Option<Self::Stdin> {
+        self.stdin.take()
+    }
+
+    fn take_stdout(&mut self) -> Option<Self::Stdout> {
+        self.stdout.take()
+    }
+
+    fn take_stderr(&mut self) -> Option<Self::Stderr> {
+        self.stderr.take()
+    }
+
+    async fn wait(self) -> Result<process::ExitStatus> {
+        if self.script.hang {
+            std::future::pending::<()>().await;
+        }
+        tokio::time::sleep(self.script.delay).await;
+        Ok(process::ExitStatus::from_raw(self.script.exitcode << 8))
+    }
+
+    async fn wait_with_output(mut self) -> Result<process::Output> {
+        let stdout = self.stdout.take().map(Cursor::into_inner);
+        let stderr = self.stderr.take().map(Cursor::into_inner);
+        Ok(process::Output {
+            status: self.wait().await?,
+            stdout: stdout.unwrap_or_default(),
+            stderr: stderr.unwrap_or_default(),
+        })
+    }
+}
+
+/// Records everything written to it in the invocation of its command.
+#[derive(Debug)]
+pub struct MockStdin {
+    invocations: Arc<Mutex<Vec<MockInvocation>>>,
+    index: usize,
+}
+
+impl AsyncWrite for MockStdin {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        self.invocations
+            .lock()
+            .map_err(|_| std::io::Error::other("mock invocations lock poisoned"))?
+            .get_mut(self.index)
+            .ok_or_else(|| std::io::Error::other("mock invocation missing"))?
+            .stdin
+            .extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::command::Command as _;
+    use crate::command::CommandFactory;
+
+    #[tokio::test]
+    async fn scripted_output_works() -> Result<()> {
+        let scripted = Target::from_str("10.0.0.1")?;
+        let other = Target::from_str("10.0.0.2")?;
+        let script = MockScript {
+            stdout: b"out".to_vec(),
+            stderr: b"err".to_vec(),
+            exitcode: 3,
+            ..Default::default()
+        };
+        let factory = MockCommandFactory::default().with(scripted.clone(), script);
+
+        let output = factory
+            .command(&scripted, OsStr::new("sh"))
+            .await?
+            .args(["-c", "true"])
+            .output()
+            .await?;
+        assert_eq!(output.stdout, b"out");
+        assert_eq!(output.stderr, b"err");
+        assert_eq!(output.status.code(), Some(3));
+
+        let output = factory
+            .command(&other, OsStr::new("sh"))
+            .await?
+            .output()
+            .await?;
+        assert!(output.stdout.is_empty());
+        assert_eq!(output.status.code(), Some(0));
+
+        let invocations = factory.invocations();
+        assert_eq!(invocations.len(), 2);
+        assert_eq!(invocations[0].target, scripted);
+        assert_eq!(invocations[0].args, vec!["-c", "true"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn spawn_error_works() -> Result<()> {
+        let target = Target::from_str("10.0.0.1")?;
+        let factory = MockCommandFactory::default().with_default(MockScript {
+            spawn_error: Some("boom".into()),
+            ..Default::default()
+        });
+
+        let result = factory
+            .command(&target, OsStr::new("sh"))
+            .await?
+            .spawn()
+            .await;
+
+        assert_eq!(
+            result.map(|_| ()).map_err(|x| x.to_string()),
+            Err("boom".into())
+        );
+        assert!(factory.invocations().is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn hang_works() -> Result<()> {
+        let target = Target::from_str("10.0.0.1")?;
+        let factory = MockCommandFactory::default().with_default(MockScript {
+            hang: true,
+            ..Default::default()
+        });
+
+        let mut cmd = factory.command(&target, OsStr::new("sh")).await?;
+        let result = tokio::time::timeout(Duration::from_millis(50), cmd.output()).await;
+
+        assert!(result.is_err());
+        Ok(())
+    }
+}
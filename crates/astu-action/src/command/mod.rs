@@ -1,15 +1,52 @@
+use std::borrow::Cow;
 use std::ffi::OsStr;
 use std::path::Path;
 use std::process::ExitStatus;
 use std::process::Output;
-use std::process::Stdio;
 
+use astu_types::Target;
 use eyre::Result;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
 
+pub mod local;
 pub mod mock;
 pub mod openssh;
+pub mod russh;
+
+/// Configuration for a standard I/O stream of a child process.
+///
+/// Unless configured otherwise, commands discard stdin and capture stdout and
+/// stderr.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stdio {
+    /// Connect the stream to a new pipe.
+    Piped,
+    /// Discard the stream.
+    Null,
+    /// Inherit the stream from the current process.
+    Inherit,
+}
 
 pub trait Child: Sized {
+    type Stdin: AsyncWrite + Unpin;
+    type Stdout: AsyncRead + Unpin;
+    type Stderr: AsyncRead + Unpin;
+
+    /// Takes the writer for stdin, if it was piped and not already taken.
+    /// Shutting it down signals end of input to the process.
+    fn take_stdin(&mut self) -> Option<Self::Stdin>;
+
+    /// Takes the reader for stdout, if it was piped and not already taken.
+    /// Output read from it will not be returned by
+    /// [`Child::wait_with_output`].
+    fn take_stdout(&mut self) -> Option<Self::Stdout>;
+
+    /// Takes the reader for stderr, if it was piped and not already taken.
+    /// Output read from it will not be returned by
+    /// [`Child::wait_with_output`].
+    fn take_stderr(&mut self) -> Option<Self::Stderr>;
+
     async fn wait(self) -> Result<ExitStatus>;
     async fn wait_with_output(self) -> Result<Output>;
 }
@@ -54,3 +91,48 @@ pub trait Command: Sized {
         self.spawn().await?.wait_with_output().await
     }
 }
+
+/// Factory for creating commands that run on targets.
+pub trait CommandFactory {
+    type Command: Command;
+
+    /// Sets up whatever is needed to run commands on the target, such as an
+    /// authenticated session, and returns a command for the program.
+    async fn command(&self, target: &Target, program: &OsStr) -> Result<Self::Command>;
+}
+
+/// All command factory implementations.
+#[derive(Debug, Clone)]
+pub enum CommandFactoryImpl {
+    Mock(mock::MockCommandFactory),
+    OpenSsh(openssh::OpenSshCommandFactory),
+    Russh(russh::RusshCommandFactory),
+}
+
+/// Quotes a word for POSIX shells, leaving it as-is if that is safe.
+#[must_use]
+pub fn quote(word: &str) -> Cow<'_, str> {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "_@%+=:,./-".contains(c);
+    if !word.is_empty() && word.chars().all(safe) {
+        return Cow::Borrowed(word);
+    }
+    Cow::Owned(format!("'{}'", word.replace('\'', r"'\''")))
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("abc", "abc")]
+    #[case("/usr/bin/env", "/usr/bin/env")]
+    #[case("", "''")]
+    #[case("a b", "'a b'")]
+    #[case("it's", r"'it'\''s'")]
+    #[case("$HOME", "'$HOME'")]
+    fn quote_works(#[case] word: &str, #[case] should: &str) {
+        assert_eq!(quote(word), should);
+    }
+}
@@ -2,17 +2,85 @@ use std::ffi::OsStr;
 use std::ops::Deref;
 use std::path::Path;
 use std::process;
+use std::sync::Arc;
+use std::time::Duration;
 
+use astu_types::Target;
+use astu_types::TargetKind;
 use eyre::Result;
+use eyre::WrapErr;
+use eyre::bail;
+use eyre::eyre;
+use openssh::KnownHosts;
 use openssh::OverSsh;
 use openssh::Session;
+use openssh::SessionBuilder;
 
 use crate::command;
+use crate::command::Stdio;
+
+/// Factory that runs commands over sessions multiplexed by the system `ssh`
+/// binary.
+///
+/// Commands are sent over the control socket of a backgrounded `ssh` rather
+/// than by spawning an `ssh` each, so that a Ctrl-C in the terminal does not
+/// reach them.
+#[derive(Debug, Clone, Copy)]
+pub struct OpenSshCommandFactory {
+    connect_timeout: Duration,
+}
+
+impl OpenSshCommandFactory {
+    #[must_use]
+    pub const fn new(connect_timeout: Duration) -> Self {
+        Self { connect_timeout }
+    }
+}
+
+impl command::CommandFactory for OpenSshCommandFactory {
+    type Command = OpenSshCommand<Arc<Session>>;
+
+    async fn command(&self, target: &Target, program: &OsStr) -> Result<Self::Command> {
+        if !matches!(
+            target.kind(),
+            TargetKind::Ip | TargetKind::Dns | TargetKind::Ssh | TargetKind::Tcp
+        ) {
+            bail!("unsupported target: {target}");
+        }
+        let host = target
+            .host()
+            .ok_or_else(|| eyre!("target has no host: {target}"))?;
+
+        let mut builder = SessionBuilder::default();
+        builder
+            .known_hosts_check(KnownHosts::Add)
+            .connect_timeout(self.connect_timeout);
+        if let Some(user) = target.user() {
+            builder.user(user.into_owned());
+        }
+        if let Some(port) = target.port() {
+            builder.port(port);
+        }
+
+        let destination = match host {
+            astu_types::Host::Ip(ip) => ip.to_string(),
+            astu_types::Host::Domain(domain) => domain,
+        };
+        let session = builder
+            .connect_mux(&destination)
+            .await
+            .wrap_err("SSH connect failed")?;
+        Ok(OpenSshCommand::new(Arc::new(session), program))
+    }
+}
 
 #[derive(Debug)]
 pub struct OpenSshCommand<S> {
     session: S,
     inner: process::Command,
+    stdin: Stdio,
+    stdout: Stdio,
+    stderr: Stdio,
 }
 
 impl<S> OpenSshCommand<S>
@@ -24,6 +92,9 @@ where
         Self {
             session,
             inner: process::Command::new(program),
+            stdin: Stdio::Null,
+            stdout: Stdio::Piped,
+            stderr: Stdio::Piped,
         }
     }
 }
@@ -78,18 +149,18 @@ where
         self
     }
 
-    fn stdin(&mut self, cfg: process::Stdio) -> &mut Self {
-        self.inner.stdin(cfg);
+    fn stdin(&mut self, cfg: Stdio) -> &mut Self {
+        self.stdin = cfg;
         self
     }
 
-    fn stdout(&mut self, cfg: process::Stdio) -> &mut Self {
-        self.inner.stdout(cfg);
+    fn stdout(&mut self, cfg: Stdio) -> &mut Self {
+        self.stdout = cfg;
         self
     }
 
-    fn stderr(&mut self, cfg: process::Stdio) -> &mut Self {
-        self.inner.stderr(cfg);
+    fn stderr(&mut self, cfg: Stdio) -> &mut Self {
+        self.stderr = cfg;
         self
     }
 
@@ -111,17 +182,47 @@ where
 
     async fn spawn(&mut self) -> Result<Self::Child> {
         let mut command = self.inner.over_ssh(self.session.clone())?;
+        command
+            .stdin(self.stdin)
+            .stdout(self.stdout)
+            .stderr(self.stderr);
         let child = command.spawn().await?;
         Ok(OpenSshChild { inner: child })
     }
 }
 
+impl From<Stdio> for openssh::Stdio {
+    fn from(value: Stdio) -> Self {
+        match value {
+            Stdio::Piped => Self::piped(),
+            Stdio::Null => Self::null(),
+            Stdio::Inherit => Self::inherit(),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct OpenSshChild<S> {
     inner: openssh::Child<S>,
 }
 
 impl<S> command::Child for OpenSshChild<S> {
+    type Stdin = openssh::ChildStdin;
+    type Stdout = openssh::ChildStdout;
+    type Stderr = openssh::ChildStderr;
+
+    fn take_stdin(&mut self) -> Option<Self::Stdin> {
+        self.inner.stdin().take()
+    }
+
+    fn take_stdout(&mut self) -> Option<Self::Stdout> {
+        self.inner.stdout().take()
+    }
+
+    fn take_stderr(&mut self) -> Option<Self::Stderr> {
+        self.inner.stderr().take()
+    }
+
     async fn wait(self) -> Result<process::ExitStatus> {
         Ok(self.inner.wait().await?)
     }
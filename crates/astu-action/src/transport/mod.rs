@@ -1,7 +1,13 @@
 //! Underlying transport used by clients.
 
+use std::net::SocketAddr;
+
+use astu_types::Host;
 use astu_types::Target;
+use astu_types::TargetKind;
 use eyre::Result;
+use eyre::WrapErr;
+use eyre::eyre;
 
 pub mod tcp;
 pub mod tcp_reuse;
@@ -16,8 +22,14 @@ pub enum Transport {
 
 /// Factory for creating transports.
 pub trait TransportFactory {
-    /// Sets up a transport to the target.
-    async fn setup(&self, target: &Target) -> Result<Transport>;
+    /// Connects a transport to a resolved address.
+    async fn connect(&self, addr: SocketAddr) -> Result<Transport>;
+
+    /// Sets up a transport to the target, resolving its address first.
+    async fn setup(&self, target: &Target) -> Result<Transport> {
+        let addr = resolve_addr(target).await?;
+        self.connect(addr).await
+    }
 }
 
 /// All transport factory implementations.
@@ -28,10 +40,73 @@ pub enum TransportFactoryImpl {
 }
 
 impl TransportFactory for TransportFactoryImpl {
-    async fn setup(&self, target: &Target) -> Result<Transport> {
+    async fn connect(&self, addr: SocketAddr) -> Result<Transport> {
         match self {
-            Self::Tcp(factory) => factory.setup(target).await,
-            Self::TcpReuse(factory) => factory.setup(target).await,
+            Self::Tcp(factory) => factory.connect(addr).await,
+            Self::TcpReuse(factory) => factory.connect(addr).await,
         }
     }
 }
+
+/// Port used for targets that do not specify one.
+pub const DEFAULT_PORT: u16 = 22;
+
+/// Resolves the remote socket address of a target, using [`DEFAULT_PORT`] if
+/// the target has no port and looking up domain names if necessary.
+///
+/// # Errors
+///
+/// If the target has no host, or looking up its domain name fails.
+pub async fn resolve_addr(target: &Target) -> Result<SocketAddr> {
+    let supported = matches!(
+        target.kind(),
+        TargetKind::Ip | TargetKind::Dns | TargetKind::Ssh | TargetKind::Tcp
+    );
+    let host = target
+        .host()
+        .filter(|_| supported)
+        .ok_or_else(|| eyre!("unsupported target: {target}"))?;
+    let port = target.port().unwrap_or(DEFAULT_PORT);
+    match host {
+        Host::Ip(ip) => Ok(SocketAddr::new(ip, port)),
+        Host::Domain(domain) => tokio::net::lookup_host((domain.as_str(), port))
+            .await
+            .wrap_err_with(|| format!("failed to look up host: {domain}"))?
+            .next()
+            .ok_or_else(|| eyre!("no addresses found for host: {domain}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("127.0.0.1", 22)]
+    #[case("127.0.0.1:2222", 2222)]
+    #[case("tcp://127.0.0.1:80", 80)]
+    #[case("ssh://localhost:2200", 2200)]
+    #[case("dns://user@localhost", 22)]
+    #[tokio::test]
+    async fn resolve_addr_works(#[case] input: &str, #[case] port: u16) -> eyre::Result<()> {
+        let target = Target::from_str(input)?;
+        let addr = resolve_addr(&target).await?;
+        assert!(addr.ip().is_loopback());
+        assert_eq!(addr.port(), port);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("cidr://127.0.0.0/8")]
+    #[case("k8s://cluster/namespace/pod")]
+    #[tokio::test]
+    async fn resolve_addr_rejects_unsupported(#[case] input: &str) -> eyre::Result<()> {
+        let target = Target::from_str(input)?;
+        assert!(resolve_addr(&target).await.is_err());
+        Ok(())
+    }
+}
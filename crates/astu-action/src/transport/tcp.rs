@@ -1,9 +1,8 @@
+use std::net::SocketAddr;
 use std::time::Duration;
 
-use astu_types::Target;
 use eyre::Result;
 use eyre::WrapErr;
-use eyre::eyre;
 use tokio::net::TcpStream;
 use tokio::time::timeout;
 
@@ -21,11 +20,7 @@ impl TransportFactory {
 }
 
 impl super::TransportFactory for TransportFactory {
-    async fn setup(&self, target: &Target) -> Result<super::Transport> {
-        let addr = target
-            .socket_addr()
-            .ok_or_else(|| eyre!("unsupported target: {target}"))?;
-
+    async fn connect(&self, addr: SocketAddr) -> Result<super::Transport> {
         let tcp = timeout(self.connect_timeout, TcpStream::connect(addr))
             .await
             .wrap_err("TCP connect timed out")?
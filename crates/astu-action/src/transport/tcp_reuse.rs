@@ -2,10 +2,8 @@ use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
 
-use astu_types::Target;
 use eyre::Result;
 use eyre::WrapErr;
-use eyre::eyre;
 use tokio::net::TcpSocket;
 use tokio::time::timeout;
 
@@ -41,11 +39,7 @@ impl TransportFactory {
 }
 
 impl super::TransportFactory for TransportFactory {
-    async fn setup(&self, target: &Target) -> Result<super::Transport> {
-        let addr = target
-            .socket_addr()
-            .ok_or_else(|| eyre!("unsupported target: {target}"))?;
-
+    async fn connect(&self, addr: SocketAddr) -> Result<super::Transport> {
         let local_addr = match addr {
             SocketAddr::V4(_) => self
                 .reserved_v4
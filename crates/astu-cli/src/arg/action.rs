@@ -1,5 +1,48 @@
+use std::collections::BTreeMap;
+use std::io::BufRead;
+use std::io::IsTerminal;
+use std::io::Read;
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use astu_action::command::CommandFactoryImpl;
+use astu_action::command::openssh::OpenSshCommandFactory;
+use astu_action::command::russh::RusshCommandFactory;
+use astu_action::transport::TransportFactoryImpl;
+use astu_action::transport::tcp;
+use astu_action::transport::tcp_reuse;
+use astu_core::Engine;
+use astu_core::GroupLimit;
+use astu_core::IdGeneratorImpl;
+use astu_core::JobPlan;
+use astu_core::SonyflakeGenerator;
+use astu_core::Spool;
+use astu_core::TaskGroup;
+use astu_core::Template;
+use astu_core::TemplateToken;
+use astu_resolve::DnsResolver;
+use astu_resolve::FileResolver;
+use astu_types::Target;
+use astu_types::TargetKind;
 use clap::Args;
 use clap::ValueEnum;
+use eyre::Result;
+use eyre::WrapErr;
+use eyre::bail;
+
+use crate::arg::DnsFlags;
+use crate::arg::GlobalFlags;
+
+/// Connect timeout used when there is no per-task timeout.
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Number of targets listed in the plan preview.
+const PLAN_SAMPLE_SIZE: usize = 5;
+
+/// Private keys in `~/.ssh` tried by the native SSH client, like `ssh` does.
+const DEFAULT_KEY_FILES: [&str; 3] = ["id_ed25519", "id_ecdsa", "id_rsa"];
 
 #[derive(Debug, Clone, Default, Args)]
 pub struct ActionFlags {
@@ -17,10 +60,18 @@ pub struct ActionFlags {
     /// Path to a file to read target URIs from.
     ///
     /// Can use `-` to read from stdin. If this is set, then `--stdin` is
-    /// assumed to be `target`.
+    /// assumed to be `target`. Lines may have `#` comments, and
+    /// `@include <path>` reads another file relative to the including one.
     #[arg(short = 'f', long, value_name = "PATH", help_heading = "Action Flags")]
     pub target_file: Vec<String>,
 
+    /// Zone file to resolve DNS names from, as well as nameservers.
+    ///
+    /// May be passed multiple times. Names with `*` or `?` wildcards expand
+    /// to every matching name in the zones.
+    #[arg(long, value_name = "PATH", help_heading = "Action Flags")]
+    pub zone_file: Vec<PathBuf>,
+
     /// How to interpret stdin.
     ///
     /// Automatically detected if not explicitly set:
@@ -44,9 +95,340 @@ pub struct ActionFlags {
     )]
     pub timeout: String,
 
-    /// Auto-accept the plan if passed target count is correct.
+    /// Auto-accept the plan if passed task count is correct.
+    ///
+    /// This is the target count, multiplied by the param count if
+    /// `--stdin=param`.
     #[arg(long, value_name = "COUNT", help_heading = "Action Flags")]
     pub confirm: Option<usize>,
+
+    /// Maximum number of tasks to run at once.
+    #[arg(
+        long,
+        default_value_t = astu_core::DEFAULT_CONCURRENCY,
+        value_name = "COUNT",
+        help_heading = "Action Flags"
+    )]
+    pub concurrency: usize,
+
+    /// Maximum number of tasks to start per second.
+    #[arg(long, value_name = "PER_SECOND", help_heading = "Action Flags")]
+    pub rate: Option<f64>,
+
+    /// Group targets for `--group-concurrency`.
+    ///
+    /// Either a network prefix length like `/24`, grouping targets by the
+    /// network of their IP address, or `domain`, grouping targets by the
+    /// parent domain of their hostname.
+    #[arg(
+        long,
+        value_name = "GROUP",
+        requires = "group_concurrency",
+        help_heading = "Action Flags"
+    )]
+    pub group_by: Option<TaskGroup>,
+
+    /// Maximum number of tasks to run at once within each group of targets.
+    #[arg(
+        long,
+        value_name = "COUNT",
+        requires = "group_by",
+        help_heading = "Action Flags"
+    )]
+    pub group_concurrency: Option<usize>,
+
+    /// SSH client used to run commands on remote targets.
+    #[arg(
+        long,
+        default_value = "openssh",
+        value_name = "CLIENT",
+        help_heading = "Action Flags"
+    )]
+    pub ssh_client: SshClient,
+
+    /// Connect to every target from the same local port.
+    ///
+    /// Avoids running out of local ports when connecting to many targets, but
+    /// only one connection to each target can be open at a time. Applies to
+    /// `--ssh-client=russh` and `ping`.
+    #[arg(long, help_heading = "Action Flags")]
+    pub tcp_reuse: bool,
+
+    #[command(flatten)]
+    pub dns: DnsFlags,
+
+    /// Job resumed by `astu resume`, instead of planning a new one.
+    #[arg(skip)]
+    pub resume: Option<String>,
+}
+
+impl ActionFlags {
+    /// Collects the seed targets passed via `--target-file` and `--target`,
+    /// defaulting to `local:` if there are none.
+    pub fn seed_targets(&self) -> Result<Vec<Target>> {
+        let mut targets = Vec::new();
+        for path in &self.target_file {
+            targets.extend(read_target_file(path)?);
+        }
+        for s in &self.target {
+            targets.push(Target::from_str(s)?);
+        }
+        if targets.is_empty() {
+            targets.push(Target::new_local()?);
+        }
+        Ok(targets)
+    }
+
+    /// Parsed per-task timeout. `None` indicates no timeout.
+    pub fn timeout(&self) -> Result<Option<Duration>> {
+        let timeout = humantime::parse_duration(&self.timeout)
+            .wrap_err_with(|| format!("invalid timeout: {}", self.timeout))?;
+        Ok(Some(timeout).filter(|x| !x.is_zero()))
+    }
+
+    /// Builds an engine with the default resolver chains, querying DNS as
+    /// configured, and the zone files.
+    pub fn engine(&self) -> Result<Engine> {
+        if let Some(rate) = self.rate {
+            if !(rate.is_finite() && rate > 0.0) {
+                bail!("--rate must be a positive number of tasks per second, not {rate}");
+            }
+            if Duration::try_from_secs_f64(1.0 / rate).is_err() {
+                bail!("--rate {rate} is too low to space task starts by");
+            }
+        }
+        let dns = self.dns.config()?;
+        let mut forward_resolver = astu_resolve::forward_chain(&dns)?;
+        let mut reverse_resolver = astu_resolve::reverse_chain(&dns)?;
+        for path in &self.zone_file {
+            let zone =
+                DnsResolver::from_zone_file(path)?.with_srv_preferred(self.dns.dns_srv_preferred);
+            forward_resolver = forward_resolver.with(zone.clone());
+            reverse_resolver = reverse_resolver.with(zone.with_forward(false).with_reverse(true));
+        }
+        let id_generator = IdGeneratorImpl::from(SonyflakeGenerator::from_hostname()?);
+        let engine = Engine::builder()
+            .id_generator(id_generator)
+            .forward_resolver(forward_resolver)
+            .reverse_resolver(reverse_resolver)
+            .concurrency(self.concurrency)
+            .maybe_timeout(self.timeout()?)
+            .maybe_rate(self.rate)
+            .maybe_group_limit(self.group_limit())
+            .build();
+        Ok(engine)
+    }
+
+    /// Per-group concurrency limit, if targets are grouped.
+    pub fn group_limit(&self) -> Option<GroupLimit> {
+        Some(GroupLimit {
+            group: self.group_by?,
+            concurrency: self.group_concurrency?,
+        })
+    }
+
+    /// Builds the transport factory used to connect to targets.
+    pub fn transport(&self) -> Result<TransportFactoryImpl> {
+        let connect_timeout = self.connect_timeout()?;
+        if self.tcp_reuse {
+            let factory = tcp_reuse::TransportFactory::try_new(connect_timeout)?;
+            return Ok(TransportFactoryImpl::TcpReuse(factory));
+        }
+        let factory = tcp::TransportFactory::new(connect_timeout);
+        Ok(TransportFactoryImpl::Tcp(factory))
+    }
+
+    /// Builds the command factory used to run commands on targets.
+    pub fn command_factory(&self) -> Result<CommandFactoryImpl> {
+        let factory = match self.ssh_client {
+            SshClient::Openssh => {
+                CommandFactoryImpl::OpenSsh(OpenSshCommandFactory::new(self.connect_timeout()?))
+            }
+            SshClient::Russh => {
+                let user = whoami::username()?;
+                let mut factory = RusshCommandFactory::new(self.transport()?, user);
+                if let Some(home) = dirs::home_dir() {
+                    for name in DEFAULT_KEY_FILES {
+                        factory = factory.with_key(home.join(".ssh").join(name));
+                    }
+                }
+                CommandFactoryImpl::Russh(factory)
+            }
+        };
+        Ok(factory)
+    }
+
+    /// Describes the plan: targets by kind with a sample of them, params, the
+    /// command as rendered for the first task, and limits.
+    pub fn print_plan(
+        &self,
+        mut out: impl Write,
+        plan: &JobPlan,
+        template: Option<&Template>,
+    ) -> Result<()> {
+        let mut kinds: BTreeMap<TargetKind, usize> = BTreeMap::new();
+        for target in &plan.targets {
+            *kinds.entry(target.kind()).or_default() += 1;
+        }
+        let kinds: Vec<_> = kinds
+            .into_iter()
+            .map(|(kind, count)| format!("{count} {kind}"))
+            .collect();
+
+        writeln!(out, "Plan for job {}:", plan.id)?;
+        writeln!(
+            out,
+            "  Targets:     {} ({})",
+            plan.targets.len(),
+            kinds.join(", ")
+        )?;
+        for target in plan.targets.iter().take(PLAN_SAMPLE_SIZE) {
+            writeln!(out, "    {target}")?;
+        }
+        if plan.targets.len() > PLAN_SAMPLE_SIZE {
+            writeln!(
+                out,
+                "    ... and {} more",
+                plan.targets.len() - PLAN_SAMPLE_SIZE
+            )?;
+        }
+        if let Some(params) = &plan.params {
+            writeln!(out, "  Params:      {}", params.len())?;
+        }
+        writeln!(out, "  Tasks:       {}", plan.task_count())?;
+        if let (Some(template), Some((target, param))) = (template, plan.tasks().first()) {
+            writeln!(out, "  Command:     {}", template.render(target, *param)?)?;
+            writeln!(out, "    (for {target})")?;
+        }
+        let timeout = self.timeout()?.map_or_else(
+            || "none".to_owned(),
+            |x| humantime::format_duration(x).to_string(),
+        );
+        writeln!(out, "  Timeout:     {timeout}")?;
+        writeln!(out, "  Concurrency: {}", self.concurrency)?;
+        if let Some(limit) = self.group_limit() {
+            writeln!(
+                out,
+                "  Per group:   {} (by {})",
+                limit.concurrency, limit.group
+            )?;
+        }
+        if let Some(rate) = self.rate {
+            writeln!(out, "  Rate:        {rate} tasks/s")?;
+        }
+        Ok(())
+    }
+
+    /// Asks for confirmation of the plan before any task starts.
+    ///
+    /// Passes without prompting if `--confirm` matches the task count.
+    /// Otherwise prompts on the terminal, failing if stdin is not a terminal.
+    ///
+    /// # Errors
+    ///
+    /// If the plan was not accepted.
+    pub fn confirm(&self, plan: &JobPlan) -> Result<()> {
+        let count = plan.task_count();
+        if count == 0 {
+            bail!("plan has no tasks");
+        }
+        match self.confirm {
+            Some(confirm) if confirm == count => return Ok(()),
+            Some(confirm) => bail!("plan has {count} tasks, but --confirm={confirm} was passed"),
+            None => {}
+        }
+
+        let stdin = std::io::stdin();
+        if !stdin.is_terminal() {
+            bail!("refusing to run non-interactively; pass --confirm={count} to accept the plan");
+        }
+        eprint!(
+            "Run {count} tasks on {} targets? [y/N] ",
+            plan.targets.len()
+        );
+        std::io::stderr().flush()?;
+        let mut answer = String::new();
+        stdin.lock().read_line(&mut answer)?;
+        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+            bail!("plan not accepted");
+        }
+        Ok(())
+    }
+
+    /// How stdin is interpreted, detecting it if not explicitly set.
+    ///
+    /// The command template, if the action has one, is used for detection.
+    pub fn stdin_mode(&self, template: Option<&Template>) -> Result<StdinMode> {
+        let reads_targets = self.target_file.iter().any(|x| x == "-");
+        let uses_param = template.is_some_and(|x| x.uses(TemplateToken::Param));
+        match self.stdin {
+            Some(StdinMode::Param | StdinMode::Pipe) if reads_targets => {
+                bail!("--stdin must be `target` when reading targets from stdin")
+            }
+            Some(mode) => Ok(mode),
+            None if uses_param && reads_targets => {
+                bail!("cannot read both params and targets from stdin")
+            }
+            None if uses_param => Ok(StdinMode::Param),
+            None if reads_targets => Ok(StdinMode::Target),
+            None => Ok(StdinMode::Pipe),
+        }
+    }
+
+    /// Reads whitespace-separated params from stdin if it is split into
+    /// params.
+    pub fn params(&self, template: Option<&Template>) -> Result<Option<Vec<String>>> {
+        if !matches!(self.stdin_mode(template)?, StdinMode::Param) {
+            return Ok(None);
+        }
+        let mut contents = String::new();
+        std::io::stdin()
+            .read_to_string(&mut contents)
+            .wrap_err("failed to read params from stdin")?;
+        let params = contents.split_whitespace().map(ToOwned::to_owned).collect();
+        Ok(Some(params))
+    }
+
+    /// Spools stdin for the tasks of a job if it is piped to them and is not a
+    /// terminal.
+    ///
+    /// Resumed jobs reuse the input spooled when the job started, if any.
+    pub async fn spool(
+        &self,
+        global: &GlobalFlags,
+        template: Option<&Template>,
+        job_id: &str,
+    ) -> Result<Option<Spool>> {
+        if self.resume.is_some() {
+            let path = global.spool_path(job_id)?;
+            if !path.exists() {
+                return Ok(None);
+            }
+            return Ok(Some(Spool::open(path).await?));
+        }
+        if !matches!(self.stdin_mode(template)?, StdinMode::Pipe) || std::io::stdin().is_terminal()
+        {
+            return Ok(None);
+        }
+        let spool = Spool::create(global.spool_path(job_id)?, tokio::io::stdin()).await?;
+        Ok(Some(spool))
+    }
+
+    /// Timeout for establishing connections to targets.
+    fn connect_timeout(&self) -> Result<Duration> {
+        Ok(self.timeout()?.unwrap_or(DEFAULT_CONNECT_TIMEOUT))
+    }
+}
+
+fn read_target_file(path: &str) -> Result<Vec<Target>> {
+    if path == "-" {
+        let mut contents = String::new();
+        std::io::stdin()
+            .read_to_string(&mut contents)
+            .wrap_err("failed to read targets from stdin")?;
+        return FileResolver.resolve_contents(&contents, "<stdin>");
+    }
+    FileResolver.resolve_file(path)
 }
 
 #[derive(Debug, Clone, Copy, Default, ValueEnum)]
@@ -61,3 +443,135 @@ pub enum StdinMode {
     #[default]
     Pipe,
 }
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum SshClient {
+    /// System `ssh` binary, multiplexed over a control master per target.
+    #[default]
+    Openssh,
+
+    /// Native client over Astu's own transports.
+    Russh,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use astu_core::IdGenerator;
+    use rstest::rstest;
+
+    use super::*;
+
+    fn flags() -> ActionFlags {
+        ActionFlags {
+            timeout: "30s".into(),
+            concurrency: 256,
+            ..Default::default()
+        }
+    }
+
+    fn plan(targets: &[&str]) -> Result<JobPlan> {
+        let targets = targets
+            .iter()
+            .map(|x| Target::from_str(x))
+            .collect::<Result<BTreeSet<_>, _>>()?;
+        Ok(JobPlan {
+            id: SonyflakeGenerator::from_hostname()?.id_now(),
+            targets,
+            params: None,
+        })
+    }
+
+    fn render(flags: &ActionFlags, plan: &JobPlan, template: Option<&Template>) -> Result<String> {
+        let mut out = Vec::new();
+        flags.print_plan(&mut out, plan, template)?;
+        let out = String::from_utf8(out)?;
+        let header = format!("Plan for job {}:\n", plan.id);
+        Ok(out.strip_prefix(&header).unwrap_or(&out).to_owned())
+    }
+
+    #[rstest]
+    #[case(0.0)]
+    #[case(-1.0)]
+    #[case(f64::NAN)]
+    #[case(f64::INFINITY)]
+    #[case(1e-20)]
+    fn engine_rejects_rate(#[case] rate: f64) {
+        let flags = ActionFlags {
+            rate: Some(rate),
+            ..flags()
+        };
+        assert!(flags.engine().is_err());
+    }
+
+    #[test]
+    fn print_plan_samples_targets_and_renders_command() -> Result<()> {
+        let flags = ActionFlags {
+            rate: Some(2.5),
+            group_by: Some(TaskGroup::from_str("/24")?),
+            group_concurrency: Some(4),
+            ..flags()
+        };
+        let plan = plan(&[
+            "10.0.0.1",
+            "10.0.0.2",
+            "10.0.0.3",
+            "10.0.0.4",
+            "10.0.0.5",
+            "web1.example.com",
+            "web2.example.com",
+        ])?
+        .with_params(vec!["a".into(), "b".into()]);
+        let template = Template::from_str("rm -rf /srv/{param} # {host}")?;
+
+        let out = render(&flags, &plan, Some(&template))?;
+
+        assert_eq!(
+            out,
+            "  Targets:     7 (2 dns, 5 ip)
+    dns://web1.example.com
+    dns://web2.example.com
+    ip://10.0.0.1
+    ip://10.0.0.2
+    ip://10.0.0.3
+    ... and 2 more
+  Params:      2
+  Tasks:       14
+  Command:     rm -rf /srv/a # web1.example.com
+    (for dns://web1.example.com)
+  Timeout:     30s
+  Concurrency: 256
+  Per group:   4 (by /24)
+  Rate:        2.5 tasks/s
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn print_plan_lists_every_target_up_to_sample_size() -> Result<()> {
+        let flags = ActionFlags {
+            timeout: "0".into(),
+            ..flags()
+        };
+        let plan = plan(&["10.0.0.1", "10.0.0.2", "10.0.0.3", "10.0.0.4", "10.0.0.5"])?;
+
+        let out = render(&flags, &plan, None)?;
+
+        assert_eq!(
+            out,
+            "  Targets:     5 (5 ip)
+    ip://10.0.0.1
+    ip://10.0.0.2
+    ip://10.0.0.3
+    ip://10.0.0.4
+    ip://10.0.0.5
+  Tasks:       5
+  Timeout:     none
+  Concurrency: 256
+"
+        );
+        Ok(())
+    }
+}
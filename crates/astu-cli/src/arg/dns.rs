@@ -0,0 +1,143 @@
+use std::net::IpAddr;
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+use astu_resolve::DnsConfig;
+use astu_resolve::LookupStrategy;
+use clap::Args;
+use clap::ValueEnum;
+use eyre::Result;
+use eyre::WrapErr;
+
+/// Port that nameservers without one are queried on.
+const DEFAULT_DNS_PORT: u16 = 53;
+
+/// Settings for querying nameservers. Unset settings are taken from the
+/// system config, such as `/etc/resolv.conf`.
+#[allow(clippy::struct_field_names)]
+#[derive(Debug, Clone, Default, Args)]
+pub struct DnsFlags {
+    /// Nameserver to query instead of the system ones, like `10.0.0.53` or
+    /// `10.0.0.53:5353`.
+    ///
+    /// May be passed multiple times, or comma separated.
+    #[arg(
+        long,
+        env = "ASTU_DNS_SERVER",
+        value_delimiter = ',',
+        value_name = "ADDR",
+        help_heading = "DNS Flags"
+    )]
+    pub dns_server: Vec<String>,
+
+    /// Address families to look up. Defaults to `both`.
+    #[arg(
+        long,
+        env = "ASTU_DNS_STRATEGY",
+        value_name = "STRATEGY",
+        help_heading = "DNS Flags"
+    )]
+    pub dns_strategy: Option<DnsStrategy>,
+
+    /// Timeout of each query in humantime.
+    #[arg(
+        long,
+        env = "ASTU_DNS_TIMEOUT",
+        value_name = "DURATION",
+        help_heading = "DNS Flags"
+    )]
+    pub dns_timeout: Option<String>,
+
+    /// Number of times that each query is attempted.
+    #[arg(
+        long,
+        env = "ASTU_DNS_ATTEMPTS",
+        value_name = "COUNT",
+        help_heading = "DNS Flags"
+    )]
+    pub dns_attempts: Option<usize>,
+
+    /// Domain to search for names that are not fully qualified, instead of the
+    /// system ones.
+    ///
+    /// May be passed multiple times, or comma separated.
+    #[arg(
+        long,
+        env = "ASTU_DNS_SEARCH",
+        value_delimiter = ',',
+        value_name = "DOMAIN",
+        help_heading = "DNS Flags"
+    )]
+    pub dns_search: Vec<String>,
+
+    /// Maximum number of responses cached.
+    #[arg(
+        long,
+        env = "ASTU_DNS_CACHE_SIZE",
+        value_name = "COUNT",
+        help_heading = "DNS Flags"
+    )]
+    pub dns_cache_size: Option<usize>,
+
+    /// Resolve SRV targets only to the records of the most preferred
+    /// (lowest) priority, leaving out backups.
+    #[arg(long, env = "ASTU_DNS_SRV_PREFERRED", help_heading = "DNS Flags")]
+    pub dns_srv_preferred: bool,
+}
+
+impl DnsFlags {
+    /// Parsed DNS settings.
+    pub fn config(&self) -> Result<DnsConfig> {
+        let nameservers = self
+            .dns_server
+            .iter()
+            .map(|x| parse_nameserver(x))
+            .collect::<Result<_>>()?;
+        let timeout = self
+            .dns_timeout
+            .as_deref()
+            .map(|x| {
+                humantime::parse_duration(x).wrap_err_with(|| format!("invalid DNS timeout: {x}"))
+            })
+            .transpose()?;
+        let config = DnsConfig::builder()
+            .nameservers(nameservers)
+            .maybe_strategy(self.dns_strategy.map(Into::into))
+            .maybe_timeout(timeout)
+            .maybe_attempts(self.dns_attempts)
+            .search(self.dns_search.clone())
+            .maybe_cache_size(self.dns_cache_size)
+            .srv_preferred(self.dns_srv_preferred)
+            .build();
+        Ok(config)
+    }
+}
+
+fn parse_nameserver(s: &str) -> Result<SocketAddr> {
+    if let Ok(ip) = IpAddr::from_str(s) {
+        return Ok(SocketAddr::new(ip, DEFAULT_DNS_PORT));
+    }
+    SocketAddr::from_str(s).wrap_err_with(|| format!("invalid DNS server: {s}"))
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum DnsStrategy {
+    /// IPv4 addresses only
+    Ipv4,
+
+    /// IPv6 addresses only
+    Ipv6,
+
+    /// Both IPv4 and IPv6 addresses
+    Both,
+}
+
+impl From<DnsStrategy> for LookupStrategy {
+    fn from(value: DnsStrategy) -> Self {
+        match value {
+            DnsStrategy::Ipv4 => Self::Ipv4,
+            DnsStrategy::Ipv6 => Self::Ipv6,
+            DnsStrategy::Both => Self::Both,
+        }
+    }
+}
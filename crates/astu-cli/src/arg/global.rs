@@ -1,7 +1,10 @@
 use std::path::PathBuf;
 
+use astu_db::Db;
 use clap::Args;
 use clap::ValueEnum;
+use eyre::Result;
+use eyre::eyre;
 
 #[derive(Debug, Clone, Default, Args)]
 pub struct GlobalFlags {
@@ -37,6 +40,29 @@ pub struct GlobalFlags {
     pub output: OutputFormat,
 }
 
+impl GlobalFlags {
+    /// Data directory, falling back to the platform default if not set.
+    pub fn data_dir(&self) -> Result<PathBuf> {
+        if let Some(data_dir) = &self.data_dir {
+            return Ok(data_dir.clone());
+        }
+        let data_dir = dirs::data_dir()
+            .ok_or_else(|| eyre!("unable to determine data directory; set --data-dir"))?
+            .join("astu");
+        Ok(data_dir)
+    }
+
+    /// Path of the file that stdin is spooled to for a job.
+    pub fn spool_path(&self, job_id: &str) -> Result<PathBuf> {
+        Ok(self.data_dir()?.join("spool").join(job_id))
+    }
+
+    /// Opens the database in the data directory, migrating it if needed.
+    pub async fn db(&self) -> Result<Db> {
+        Db::open(&self.data_dir()?).await
+    }
+}
+
 #[derive(Debug, Clone, Copy, Default, ValueEnum)]
 pub enum OutputFormat {
     #[default]
@@ -1,8 +1,13 @@
 mod action;
+mod dns;
 mod global;
 mod result;
 
 pub use action::ActionFlags;
+pub use dns::DnsFlags;
 pub use global::GlobalFlags;
+pub use global::OutputFormat;
 pub use result::ResultField;
 pub use result::ResultFlags;
+pub use result::TargetFilter;
+pub use result::TaskStatus;
@@ -1,5 +1,10 @@
+use std::str::FromStr;
+
+use astu_db::Db;
+use astu_types::Target;
 use clap::Args;
 use clap::ValueEnum;
+use eyre::Result;
 
 #[derive(Debug, Clone, Default, Args)]
 pub struct ResultFlags {
@@ -15,6 +20,13 @@ pub struct ResultFlags {
     pub job: Option<String>,
 }
 
+impl ResultFlags {
+    /// ID of the job to display results for, defaulting to the latest job.
+    pub async fn job_id(&self, db: &Db) -> Result<String> {
+        db.job_or_latest(self.job.as_deref()).await
+    }
+}
+
 #[derive(Debug, Clone, Copy, ValueEnum)]
 pub enum ResultField {
     /// Task outcome
@@ -28,4 +40,84 @@ pub enum ResultField {
 
     /// Task exit code
     Exitcode,
+
+    /// Task error
+    Error,
+}
+
+impl ResultField {
+    /// Name of the field, as passed on the command line.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Status => "status",
+            Self::Stdout => "stdout",
+            Self::Stderr => "stderr",
+            Self::Exitcode => "exitcode",
+            Self::Error => "error",
+        }
+    }
+}
+
+impl From<ResultField> for astu_db::Field {
+    fn from(value: ResultField) -> Self {
+        match value {
+            ResultField::Status => Self::Status,
+            ResultField::Stdout => Self::Stdout,
+            ResultField::Stderr => Self::Stderr,
+            ResultField::Exitcode => Self::Exitcode,
+            ResultField::Error => Self::Error,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TaskStatus {
+    /// Not yet started
+    Pending,
+
+    /// Currently running
+    Running,
+
+    /// Finished without error
+    Complete,
+
+    /// Finished with an error
+    Failed,
+
+    /// Canceled before it could start
+    Canceled,
+}
+
+impl From<TaskStatus> for astu_db::TaskStatus {
+    fn from(value: TaskStatus) -> Self {
+        match value {
+            TaskStatus::Pending => Self::Pending,
+            TaskStatus::Running => Self::Running,
+            TaskStatus::Complete => Self::Complete,
+            TaskStatus::Failed => Self::Failed,
+            TaskStatus::Canceled => Self::Canceled,
+        }
+    }
+}
+
+/// Targets passed with `-T` to select tasks by. Selects every task if empty.
+#[derive(Debug, Clone, Default)]
+pub struct TargetFilter {
+    targets: Vec<Target>,
+}
+
+impl TargetFilter {
+    /// Parses target URIs or short forms.
+    pub fn parse(values: &[String]) -> Result<Self> {
+        let targets = values
+            .iter()
+            .map(|x| Target::from_str(x))
+            .collect::<Result<_>>()?;
+        Ok(Self { targets })
+    }
+
+    /// Whether a task with the target is selected. See [`Target::matches`].
+    pub fn matches(&self, target: &Target) -> bool {
+        self.targets.is_empty() || self.targets.iter().any(|x| x.matches(target))
+    }
 }
@@ -1,5 +1,13 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use astu_db::Db;
 use clap::Args;
+use serde::Serialize;
+use tabled::Tabled;
 
+use crate::arg::GlobalFlags;
+use crate::arg::OutputFormat;
 use crate::arg::ResultField;
 use crate::arg::ResultFlags;
 
@@ -19,13 +27,101 @@ pub struct Freq {
     /// Restrict output to these fields.
     #[arg(
         value_name = "FIELD",
-        default_values = ["status", "stdout", "stderr", "exitcode"]
+        default_values = ["status", "stdout", "stderr", "exitcode", "error"]
     )]
     pub field: Vec<ResultField>,
 }
 
 impl crate::Run for Freq {
-    async fn run(&self) -> eyre::Result<()> {
-        eyre::bail!("unimplemented")
+    async fn run(&self, global: &GlobalFlags) -> eyre::Result<()> {
+        let db = global.db().await?;
+        let job_id = self.result.job_id(&db).await?;
+        print_freq(global, &db, &job_id, &self.field, self.contains.as_deref()).await
+    }
+}
+
+#[derive(Debug, Serialize, Tabled)]
+struct FreqRow {
+    #[tabled(display = "display_value")]
+    value: String,
+    count: u64,
+    #[tabled(display = "display_pct")]
+    pct: f64,
+}
+
+/// Drops the final line break of output, which would otherwise show as an
+/// empty line in the table.
+fn display_value(value: &str) -> String {
+    value.strip_suffix('\n').unwrap_or(value).to_owned()
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn display_pct(pct: &f64) -> String {
+    format!("{pct:.0}%")
+}
+
+/// Prints a frequency table for each field in a job, optionally only with
+/// values containing a string. Percentages are always of all tasks.
+pub async fn print_freq(
+    global: &GlobalFlags,
+    db: &Db,
+    job_id: &str,
+    fields: &[ResultField],
+    contains: Option<&str>,
+) -> eyre::Result<()> {
+    let total = db.task_count(job_id).await?;
+    let mut tables = BTreeMap::new();
+    for &field in fields {
+        let rows: Vec<_> = db
+            .freq(job_id, field.into())
+            .await?
+            .into_iter()
+            .filter(|x| contains.is_none_or(|s| x.value.contains(s)))
+            .map(|x| FreqRow {
+                value: x.value,
+                count: x.count,
+                pct: percent(x.count, total),
+            })
+            .collect();
+        tables.insert(field_title(field), rows);
+    }
+
+    let mut stdout = std::io::stdout().lock();
+    match global.output {
+        OutputFormat::Text => {
+            for (i, &field) in fields.iter().enumerate() {
+                let Some(rows) = tables.remove(field_title(field)) else {
+                    continue;
+                };
+                if i > 0 {
+                    writeln!(stdout)?;
+                }
+                writeln!(stdout, "{}", field_title(field))?;
+                writeln!(stdout, "{}", crate::table::render(rows))?;
+            }
+        }
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut stdout, &tables)?;
+            writeln!(stdout)?;
+        }
+    }
+    Ok(())
+}
+
+const fn field_title(field: ResultField) -> &'static str {
+    match field {
+        ResultField::Status => "status",
+        ResultField::Stdout => "stdout",
+        ResultField::Stderr => "stderr",
+        ResultField::Exitcode => "exitcode",
+        ResultField::Error => "error-freq",
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn percent(count: u64, total: u64) -> f64 {
+    if total == 0 {
+        return 0.0;
     }
+    count as f64 * 100.0 / total as f64
 }
@@ -1,17 +1,200 @@
+use std::io::Write;
+use std::path::PathBuf;
+
+use chrono::DateTime;
+use chrono::Utc;
+use clap::ArgGroup;
 use clap::Args;
+use eyre::WrapErr;
+use serde::Serialize;
+
+use crate::arg::GlobalFlags;
+use crate::arg::OutputFormat;
+use crate::table::format_bytes;
 
 /// Clean old jobs and associated data from the database
 ///
 /// Cleans the database of jobs and their associated data.
 #[derive(Debug, Args)]
+#[command(group(ArgGroup::new("retention").required(true).multiple(true)))]
 pub struct Gc {
     /// Delete data that was collected at this age or older.
-    #[arg(long, value_name = "DURATION")]
+    #[arg(long, value_name = "DURATION", group = "retention")]
     pub before: Option<String>,
+
+    /// Delete all but this many of the newest jobs.
+    #[arg(long, value_name = "COUNT", group = "retention")]
+    pub keep: Option<usize>,
+
+    /// Report what would be deleted without deleting anything.
+    #[arg(short = 'n', long)]
+    pub dry_run: bool,
+}
+
+/// What was, or would be, deleted.
+#[derive(Debug, Default, Serialize)]
+struct GcReport {
+    dry_run: bool,
+    job_ids: Vec<String>,
+    task_count: u64,
+    files: Vec<PathBuf>,
+    file_bytes: u64,
+    /// Bytes returned by vacuuming the database, which are only known once
+    /// it has been vacuumed.
+    db_bytes: Option<u64>,
 }
 
 impl crate::Run for Gc {
-    async fn run(&self) -> eyre::Result<()> {
-        eyre::bail!("unimplemented")
+    async fn run(&self, global: &GlobalFlags) -> eyre::Result<()> {
+        let report = self.collect(global, Utc::now()).await?;
+        let mut stdout = std::io::stdout().lock();
+        match global.output {
+            OutputFormat::Text => writeln!(stdout, "{}", report.summary())?,
+            OutputFormat::Json => {
+                serde_json::to_writer_pretty(&mut stdout, &report)?;
+                writeln!(stdout)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Gc {
+    /// Selects the jobs to delete as of `now`, and deletes them with their
+    /// files unless this is a dry run.
+    async fn collect(&self, global: &GlobalFlags, now: DateTime<Utc>) -> eyre::Result<GcReport> {
+        let db = global.db().await?;
+        let cutoff = self
+            .before
+            .as_deref()
+            .map(|x| -> eyre::Result<_> {
+                let age = humantime::parse_duration(x)
+                    .wrap_err_with(|| format!("invalid --before duration: {x}"))?;
+                Ok(now - chrono::Duration::from_std(age)?)
+            })
+            .transpose()?;
+
+        // Newest first, so that the jobs to keep come first.
+        let mut jobs = db.job_summaries().await?;
+        jobs.reverse();
+        let mut report = GcReport {
+            dry_run: self.dry_run,
+            ..Default::default()
+        };
+        for (i, job) in jobs.iter().enumerate() {
+            let too_old = cutoff.is_some_and(|x| job.job.started_at <= x);
+            let too_many = self.keep.is_some_and(|x| i >= x);
+            if !too_old && !too_many {
+                continue;
+            }
+            report.job_ids.push(job.job.id.clone());
+            report.task_count += job.task_count();
+            let path = global.spool_path(&job.job.id)?;
+            if let Ok(metadata) = std::fs::metadata(&path) {
+                report.file_bytes += metadata.len();
+                report.files.push(path);
+            }
+        }
+
+        if !self.dry_run {
+            let size = db.size().await?;
+            db.delete_jobs(&report.job_ids).await?;
+            for path in &report.files {
+                std::fs::remove_file(path)
+                    .wrap_err_with(|| format!("failed to delete {}", path.display()))?;
+            }
+            db.vacuum().await?;
+            report.db_bytes = Some(size.saturating_sub(db.size().await?));
+        }
+        Ok(report)
+    }
+}
+
+impl GcReport {
+    fn summary(&self) -> String {
+        let deleted = format!(
+            "{} jobs with {} tasks, and {} files",
+            self.job_ids.len(),
+            self.task_count,
+            self.files.len(),
+        );
+        self.db_bytes.map_or_else(
+            || {
+                format!(
+                    "Would delete {deleted}, reclaiming {} of files and more from the database",
+                    format_bytes(self.file_bytes),
+                )
+            },
+            |db_bytes| {
+                format!(
+                    "Deleted {deleted}, reclaiming {} ({} of files, {} from the database)",
+                    format_bytes(self.file_bytes + db_bytes),
+                    format_bytes(self.file_bytes),
+                    format_bytes(db_bytes),
+                )
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use astu_db::JobRecord;
+    use chrono::TimeDelta;
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rustfmt::skip::attributes(case)]
+    #[rstest]
+    #[case(Some("3d"),  None,    &["j2", "j1"])]
+    #[case(None,        Some(1), &["j3", "j2", "j1"])]
+    #[case(Some("3d"),  Some(3), &["j2", "j1"])]
+    #[case(Some("12h"), Some(3), &["j3", "j2", "j1"])]
+    #[case(Some("30d"), Some(4), &[])]
+    #[tokio::test]
+    async fn collect_works(
+        #[case] before: Option<&str>,
+        #[case] keep: Option<usize>,
+        #[case] should: &[&str],
+        #[values(false, true)] dry_run: bool,
+    ) -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let global = GlobalFlags {
+            data_dir: Some(dir.path().to_owned()),
+            ..Default::default()
+        };
+        let db = global.db().await?;
+        let now = Utc::now();
+        for (id, days_ago) in [("j1", 10), ("j2", 5), ("j3", 1), ("j4", 0)] {
+            db.insert_job(&JobRecord {
+                id: id.into(),
+                started_at: now - TimeDelta::days(days_ago),
+                cmdline: vec!["astu".into(), "ping".into()],
+                plan: serde_json::json!({}),
+            })
+            .await?;
+            let spool = global.spool_path(id)?;
+            std::fs::create_dir_all(dir.path().join("spool"))?;
+            std::fs::write(spool, "input")?;
+        }
+        let gc = Gc {
+            before: before.map(Into::into),
+            keep,
+            dry_run,
+        };
+
+        let report = gc.collect(&global, now).await?;
+
+        assert_eq!(report.job_ids, should);
+        assert_eq!(report.files.len(), should.len());
+        assert_eq!(report.file_bytes, 5 * should.len() as u64);
+        assert_eq!(report.db_bytes.is_none(), dry_run);
+        for id in ["j1", "j2", "j3", "j4"] {
+            let kept = dry_run || !should.contains(&id);
+            assert_eq!(db.job(id).await?.is_some(), kept);
+            assert_eq!(global.spool_path(id)?.exists(), kept);
+        }
+        Ok(())
     }
 }
@@ -1,13 +1,127 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use astu_db::JobSummary;
+use chrono::DateTime;
+use chrono::Utc;
 use clap::Args;
+use clap::ValueEnum;
+use serde::Serialize;
+use tabled::Tabled;
+
+use crate::arg::GlobalFlags;
+use crate::arg::OutputFormat;
+use crate::arg::TaskStatus;
 
 /// Display jobs and job metadata
 ///
 /// Displays a table of jobs and their metadata.
 #[derive(Debug, Args)]
-pub struct Jobs {}
+pub struct Jobs {
+    /// Sort jobs by this key.
+    #[arg(long, value_enum, default_value_t)]
+    pub sort: JobSort,
+
+    /// Reverse the sort order.
+    #[arg(short, long)]
+    pub reverse: bool,
+
+    /// Display at most this many jobs, after sorting.
+    #[arg(short = 'n', long)]
+    pub limit: Option<usize>,
+
+    /// Only display jobs with tasks in any of these statuses.
+    #[arg(short, long, value_delimiter = ',')]
+    pub status: Vec<TaskStatus>,
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum JobSort {
+    /// Newest first
+    #[default]
+    Started,
+
+    /// Most tasks first
+    Tasks,
+
+    /// Most failed tasks first
+    Failed,
+}
 
 impl crate::Run for Jobs {
-    async fn run(&self) -> eyre::Result<()> {
-        eyre::bail!("unimplemented")
+    async fn run(&self, global: &GlobalFlags) -> eyre::Result<()> {
+        let db = global.db().await?;
+        let mut jobs: Vec<_> = db
+            .job_summaries()
+            .await?
+            .into_iter()
+            .filter(|job| {
+                self.status.is_empty() || self.status.iter().any(|&x| job.count(x.into()) > 0)
+            })
+            .collect();
+
+        // Ties keep the newest job first.
+        jobs.reverse();
+        match self.sort {
+            JobSort::Started => {}
+            JobSort::Tasks => jobs.sort_by_key(|x| Reverse(x.task_count())),
+            JobSort::Failed => jobs.sort_by_key(|x| Reverse(x.count(astu_db::TaskStatus::Failed))),
+        }
+        if self.reverse {
+            jobs.reverse();
+        }
+        jobs.truncate(self.limit.unwrap_or(usize::MAX));
+        let rows: Vec<_> = jobs.into_iter().map(JobRow::from).collect();
+
+        let mut stdout = std::io::stdout().lock();
+        match global.output {
+            OutputFormat::Text => writeln!(stdout, "{}", crate::table::render(rows))?,
+            OutputFormat::Json => {
+                serde_json::to_writer_pretty(&mut stdout, &rows)?;
+                writeln!(stdout)?;
+            }
+        }
+        Ok(())
     }
 }
+
+#[derive(Debug, Serialize, Tabled)]
+struct JobRow {
+    job_id: String,
+    #[tabled(display = "display_time")]
+    started_at: DateTime<Utc>,
+    command: String,
+    task_count: u64,
+    failed: u64,
+    #[tabled(skip)]
+    task_counts: BTreeMap<astu_db::TaskStatus, u64>,
+    #[tabled(skip)]
+    cmdline: Vec<String>,
+}
+
+impl From<JobSummary> for JobRow {
+    fn from(summary: JobSummary) -> Self {
+        let task_count = summary.task_count();
+        let failed = summary.count(astu_db::TaskStatus::Failed);
+        let job = summary.job;
+        // Actions describe their command in the plan, if they have one.
+        let command = ["command", "action"]
+            .iter()
+            .find_map(|&key| job.plan.get(key)?.as_str())
+            .map_or_else(|| job.cmdline.join(" "), ToOwned::to_owned);
+        Self {
+            task_count,
+            failed,
+            task_counts: summary.task_counts,
+            job_id: job.id,
+            started_at: job.started_at,
+            command,
+            cmdline: job.cmdline,
+        }
+    }
+}
+
+fn display_time(time: &DateTime<Utc>) -> String {
+    time.format("%Y-%m-%d %H:%M:%S%.6f").to_string()
+}
@@ -1,5 +1,10 @@
+use std::io::Write;
+
 use clap::Args;
 
+use crate::arg::GlobalFlags;
+use crate::arg::OutputFormat;
+
 /// Resolve targets
 ///
 /// Expands a set of input targets into a set of actionable targets. Does not
@@ -11,7 +16,23 @@ pub struct Lookup {
 }
 
 impl crate::Run for Lookup {
-    async fn run(&self) -> eyre::Result<()> {
-        eyre::bail!("unimplemented")
+    async fn run(&self, global: &GlobalFlags) -> eyre::Result<()> {
+        let engine = self.action.engine()?;
+        let plan = engine.job_plan(self.action.seed_targets()?).await;
+
+        let mut stdout = std::io::stdout().lock();
+        match global.output {
+            OutputFormat::Text => {
+                for target in &plan.targets {
+                    writeln!(stdout, "{target}")?;
+                }
+            }
+            OutputFormat::Json => {
+                let targets: Vec<_> = plan.targets.iter().map(ToString::to_string).collect();
+                serde_json::to_writer_pretty(&mut stdout, &targets)?;
+                writeln!(stdout)?;
+            }
+        }
+        Ok(())
     }
 }
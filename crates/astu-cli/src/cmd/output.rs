@@ -1,7 +1,17 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use astu_db::ResultRecord;
+use astu_db::TaskRecord;
 use clap::Args;
+use eyre::Result;
+use serde_json::Value;
 
+use crate::arg::GlobalFlags;
+use crate::arg::OutputFormat;
 use crate::arg::ResultField;
 use crate::arg::ResultFlags;
+use crate::arg::TargetFilter;
 
 /// Display per-task captured output for a job
 ///
@@ -12,23 +22,126 @@ pub struct Output {
     pub result: ResultFlags,
 
     /// Target URI filter.
+    ///
+    /// Selects tasks whose target has the same host, or an IP address within
+    /// the network if a CIDR. May be passed multiple times.
     #[arg(short = 'T', long, value_name = "TARGET")]
     pub target: Vec<String>,
 
     /// Filter rows to values containing this string.
-    #[arg(long)]
+    #[arg(long, visible_alias = "contains")]
     pub value: Option<String>,
 
+    /// Display output as captured, before deduplication.
+    #[arg(long)]
+    pub raw: bool,
+
     /// Restrict output to these fields.
     #[arg(
         value_name = "FIELD",
-        default_values = ["status", "stdout", "stderr", "exitcode"]
+        default_values = ["status", "stdout", "stderr", "exitcode", "error"]
     )]
     pub field: Vec<ResultField>,
 }
 
 impl crate::Run for Output {
-    async fn run(&self) -> eyre::Result<()> {
-        eyre::bail!("unimplemented")
+    async fn run(&self, global: &GlobalFlags) -> Result<()> {
+        let db = global.db().await?;
+        let job_id = self.result.job_id(&db).await?;
+        let filter = TargetFilter::parse(&self.target)?;
+
+        let mut results: HashMap<_, _> = db
+            .results(&job_id)
+            .await?
+            .into_iter()
+            .map(|x| (x.task_id.clone(), x))
+            .collect();
+        let mut rows = Vec::new();
+        for task in db.tasks(&job_id).await? {
+            if !filter.matches(&task.target) {
+                continue;
+            }
+            let result = results.remove(&task.id).unwrap_or_default();
+            let values: Vec<_> = self
+                .field
+                .iter()
+                .map(|&field| field_value(field, &task, &result, self.raw))
+                .collect();
+            if let Some(needle) = &self.value
+                && !values.iter().any(|x| display_value(x).contains(needle))
+            {
+                continue;
+            }
+            rows.push((task, values));
+        }
+
+        let mut stdout = std::io::stdout().lock();
+        match global.output {
+            OutputFormat::Text => {
+                let with_param = rows.iter().any(|(task, _)| task.param.is_some());
+                let mut header = vec!["target".to_owned()];
+                if with_param {
+                    header.push("param".to_owned());
+                }
+                header.extend(self.field.iter().map(|x| x.name().to_owned()));
+                let rows = rows
+                    .into_iter()
+                    .map(|(task, values)| {
+                        let mut row = vec![task.target.to_string()];
+                        if with_param {
+                            row.push(task.param.unwrap_or_default());
+                        }
+                        row.extend(values.iter().map(display_value));
+                        row
+                    })
+                    .collect();
+                writeln!(stdout, "{}", crate::table::render_columns(header, rows))?;
+            }
+            OutputFormat::Json => {
+                let records: Vec<_> = rows
+                    .into_iter()
+                    .map(|(task, values)| {
+                        let mut record = serde_json::Map::new();
+                        record.insert("task_id".into(), task.id.into());
+                        record.insert("target".into(), task.target.to_string().into());
+                        record.insert("param".into(), task.param.into());
+                        for (field, value) in self.field.iter().zip(values) {
+                            record.insert(field.name().into(), value);
+                        }
+                        record
+                    })
+                    .collect();
+                serde_json::to_writer_pretty(&mut stdout, &records)?;
+                writeln!(stdout)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Value of a field for a task, or null if it has none.
+fn field_value(field: ResultField, task: &TaskRecord, result: &ResultRecord, raw: bool) -> Value {
+    let output = |output: &Option<String>, raw_output: &Option<String>| {
+        if raw {
+            raw_output.clone().or_else(|| output.clone()).into()
+        } else {
+            output.clone().into()
+        }
+    };
+    match field {
+        ResultField::Status => task.status.to_string().into(),
+        ResultField::Stdout => output(&result.stdout, &result.raw_stdout),
+        ResultField::Stderr => output(&result.stderr, &result.raw_stderr),
+        ResultField::Exitcode => result.exitcode.into(),
+        ResultField::Error => result.error.clone().into(),
+    }
+}
+
+/// Displays a value in a table cell, without the final line break of output.
+fn display_value(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(x) => x.strip_suffix('\n').unwrap_or(x).to_owned(),
+        x => x.to_string(),
     }
 }
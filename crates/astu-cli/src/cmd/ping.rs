@@ -1,6 +1,8 @@
+use astu_core::Action;
 use clap::Args;
 
 use crate::arg::ActionFlags;
+use crate::arg::GlobalFlags;
 
 /// Ping targets
 ///
@@ -17,7 +19,10 @@ pub struct Ping {
 }
 
 impl crate::Run for Ping {
-    async fn run(&self) -> eyre::Result<()> {
-        eyre::bail!("unimplemented")
+    async fn run(&self, global: &GlobalFlags) -> eyre::Result<()> {
+        let action = Action::Ping {
+            transport: self.action.transport()?,
+        };
+        super::execute(global, &self.action, None, async |_| Ok(action)).await
     }
 }
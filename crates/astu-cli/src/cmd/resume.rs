@@ -1,11 +1,62 @@
+use astu_db::TaskStatus;
 use clap::Args;
+use clap::Parser;
+use eyre::WrapErr;
+use eyre::bail;
+
+use super::Command;
+use crate::Cli;
+use crate::arg::GlobalFlags;
+use crate::arg::ResultFlags;
 
 /// Resume a previously canceled job
+///
+/// Runs the tasks of a job that were canceled by Ctrl-C, under the same job ID
+/// and with the same flags as the command that started it.
 #[derive(Debug, Args)]
-pub struct Resume {}
+pub struct Resume {
+    #[command(flatten)]
+    pub result: ResultFlags,
+}
 
 impl crate::Run for Resume {
-    async fn run(&self) -> eyre::Result<()> {
-        eyre::bail!("unimplemented")
+    async fn run(&self, global: &GlobalFlags) -> eyre::Result<()> {
+        let db = global.db().await?;
+        let job_id = self.result.job_id(&db).await?;
+        let Some(job) = db.job(&job_id).await? else {
+            bail!("job not found: {job_id}");
+        };
+        let canceled = db
+            .tasks(&job_id)
+            .await?
+            .iter()
+            .filter(|x| x.status == TaskStatus::Canceled)
+            .count();
+        if canceled == 0 {
+            bail!("job {job_id} has no canceled tasks to resume");
+        }
+
+        let mut cli = Cli::try_parse_from(&job.cmdline)
+            .wrap_err_with(|| format!("failed to parse the command of job {job_id}"))?;
+        let command: Vec<_> = job
+            .cmdline
+            .iter()
+            .skip(1)
+            .map(|x| astu_action::command::quote(x))
+            .collect();
+        eprintln!("Resuming job: {job_id}");
+        eprintln!("Command: astu {}", command.join(" "));
+
+        match &mut cli.command {
+            Command::Ping(inner) => {
+                inner.action.resume = Some(job_id);
+                inner.run(global).await
+            }
+            Command::Run(inner) => {
+                inner.action.resume = Some(job_id);
+                inner.run(global).await
+            }
+            _ => bail!("job {job_id} was not started by an action and cannot be resumed"),
+        }
     }
 }
@@ -1,7 +1,10 @@
+use astu_core::Action;
+use astu_core::Template;
 use clap::Args;
 use clap::ValueEnum;
 
 use crate::arg::ActionFlags;
+use crate::arg::GlobalFlags;
 
 /// Run a command on targets
 ///
@@ -27,6 +30,7 @@ pub struct Run {
     /// Deduplicators for line normalization.
     ///
     /// These values will be substituted for their template tokens when seen.
+    /// The raw output is kept as well.
     #[arg(
         long,
         value_delimiter = ',',
@@ -44,8 +48,32 @@ pub enum TemplateToken {
     Ip,
 }
 
+impl From<TemplateToken> for astu_core::TemplateToken {
+    fn from(value: TemplateToken) -> Self {
+        match value {
+            TemplateToken::Param => Self::Param,
+            TemplateToken::Host => Self::Host,
+            TemplateToken::User => Self::User,
+            TemplateToken::Ip => Self::Ip,
+        }
+    }
+}
+
 impl crate::Run for Run {
-    async fn run(&self) -> eyre::Result<()> {
-        eyre::bail!("unimplemented")
+    async fn run(&self, global: &GlobalFlags) -> eyre::Result<()> {
+        let factory = self.action.command_factory()?;
+        let command: Template = self.command.parse()?;
+        let template = Some(&command);
+        super::execute(global, &self.action, template, async |job_id| {
+            let stdin = self.action.spool(global, template, job_id).await?;
+            Ok(Action::Run {
+                factory,
+                command: command.clone(),
+                live: self.live,
+                stdin,
+                dedupe: self.dedupe.iter().copied().map(Into::into).collect(),
+            })
+        })
+        .await
     }
 }
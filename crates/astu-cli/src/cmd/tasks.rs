@@ -1,6 +1,18 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::Duration;
+
 use clap::Args;
+use clap::ValueEnum;
+use serde::Serialize;
+use serde::Serializer;
 
+use crate::arg::GlobalFlags;
+use crate::arg::OutputFormat;
 use crate::arg::ResultFlags;
+use crate::arg::TargetFilter;
+use crate::arg::TaskStatus;
 
 /// Display tasks and task metadata for a job
 ///
@@ -9,10 +21,133 @@ use crate::arg::ResultFlags;
 pub struct Tasks {
     #[command(flatten)]
     pub result: ResultFlags,
+
+    /// Target URI filter.
+    #[arg(short = 'T', long, value_name = "TARGET")]
+    pub target: Vec<String>,
+
+    /// Only display tasks in any of these statuses.
+    #[arg(short, long, value_delimiter = ',')]
+    pub status: Vec<TaskStatus>,
+
+    /// Sort tasks by this key.
+    #[arg(long, value_enum, default_value_t)]
+    pub sort: TaskSort,
+
+    /// Reverse the sort order.
+    #[arg(short, long)]
+    pub reverse: bool,
+
+    /// Display at most this many tasks, after sorting.
+    #[arg(short = 'n', long)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum TaskSort {
+    /// Target, then param
+    #[default]
+    Target,
+
+    /// Lifecycle order, from pending to canceled
+    Status,
+
+    /// Slowest first
+    Duration,
 }
 
 impl crate::Run for Tasks {
-    async fn run(&self) -> eyre::Result<()> {
-        eyre::bail!("unimplemented")
+    async fn run(&self, global: &GlobalFlags) -> eyre::Result<()> {
+        let db = global.db().await?;
+        let job_id = self.result.job_id(&db).await?;
+        let filter = TargetFilter::parse(&self.target)?;
+        let statuses: Vec<astu_db::TaskStatus> = self.status.iter().map(|&x| x.into()).collect();
+
+        let mut spans: HashMap<_, (_, _)> = HashMap::new();
+        for phase in db.phases(&job_id).await? {
+            let span = spans
+                .entry(phase.task_id)
+                .or_insert((phase.started_at, phase.ended_at));
+            span.0 = span.0.min(phase.started_at);
+            span.1 = span.1.max(phase.ended_at);
+        }
+        let mut tasks: Vec<_> = db
+            .tasks(&job_id)
+            .await?
+            .into_iter()
+            .filter(|task| filter.matches(&task.target))
+            .filter(|task| statuses.is_empty() || statuses.contains(&task.status))
+            .map(|task| TaskRow {
+                duration: spans
+                    .get(&task.id)
+                    .and_then(|(start, end)| (*end - *start).to_std().ok()),
+                task_id: task.id,
+                target: task.target.to_string(),
+                param: task.param,
+                status: task.status,
+            })
+            .collect();
+
+        // Tasks are already sorted by target, and ties keep that order.
+        match self.sort {
+            TaskSort::Target => {}
+            TaskSort::Status => tasks.sort_by_key(|x| x.status),
+            TaskSort::Duration => tasks.sort_by_key(|x| Reverse(x.duration)),
+        }
+        if self.reverse {
+            tasks.reverse();
+        }
+        tasks.truncate(self.limit.unwrap_or(usize::MAX));
+
+        let mut stdout = std::io::stdout().lock();
+        match global.output {
+            OutputFormat::Text => {
+                let with_param = tasks.iter().any(|x| x.param.is_some());
+                let mut header = vec!["task_id", "target"];
+                if with_param {
+                    header.push("param");
+                }
+                header.extend(["status", "duration"]);
+                let rows = tasks
+                    .into_iter()
+                    .map(|task| {
+                        let mut row = vec![task.task_id, task.target];
+                        if with_param {
+                            row.push(task.param.unwrap_or_default());
+                        }
+                        row.push(task.status.to_string());
+                        row.push(
+                            task.duration
+                                .map(crate::table::format_duration)
+                                .unwrap_or_default(),
+                        );
+                        row
+                    })
+                    .collect();
+                let header = header.into_iter().map(ToOwned::to_owned).collect();
+                writeln!(stdout, "{}", crate::table::render_columns(header, rows))?;
+            }
+            OutputFormat::Json => {
+                serde_json::to_writer_pretty(&mut stdout, &tasks)?;
+                writeln!(stdout)?;
+            }
+        }
+        Ok(())
     }
 }
+
+#[derive(Debug, Serialize)]
+struct TaskRow {
+    task_id: String,
+    target: String,
+    param: Option<String>,
+    status: astu_db::TaskStatus,
+    /// Time from the start of the first phase to the end of the last one.
+    #[serde(rename = "duration_secs", serialize_with = "serialize_secs")]
+    duration: Option<Duration>,
+}
+
+#[allow(clippy::ref_option)]
+fn serialize_secs<S: Serializer>(duration: &Option<Duration>, s: S) -> Result<S::Ok, S::Error> {
+    duration.map(|x| x.as_secs_f64()).serialize(s)
+}
@@ -1,6 +1,19 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::Write;
+use std::time::Duration;
+
+use astu_db::PhaseRecord;
 use clap::Args;
+use serde::Serialize;
 
+use crate::arg::GlobalFlags;
+use crate::arg::OutputFormat;
 use crate::arg::ResultFlags;
+use crate::arg::TargetFilter;
+
+/// Width of the timeline of each task, in columns.
+const TIMELINE_WIDTH: usize = 40;
 
 /// Display diagnostic timing traces for tasks in a job
 ///
@@ -17,7 +30,189 @@ pub struct Trace {
 }
 
 impl crate::Run for Trace {
-    async fn run(&self) -> eyre::Result<()> {
-        eyre::bail!("unimplemented")
+    async fn run(&self, global: &GlobalFlags) -> eyre::Result<()> {
+        let db = global.db().await?;
+        let job_id = self.result.job_id(&db).await?;
+        let filter = TargetFilter::parse(&self.target)?;
+
+        let mut phases: HashMap<_, Vec<_>> = HashMap::new();
+        for phase in db.phases(&job_id).await? {
+            phases.entry(phase.task_id.clone()).or_default().push(phase);
+        }
+        let traces: Vec<_> = db
+            .tasks(&job_id)
+            .await?
+            .into_iter()
+            .filter(|task| filter.matches(&task.target))
+            .map(|task| TaskTrace {
+                phases: phases.remove(&task.id).unwrap_or_default(),
+                task_id: task.id,
+                target: task.target.to_string(),
+                param: task.param,
+                status: task.status.to_string(),
+            })
+            .collect();
+
+        let mut stdout = std::io::stdout().lock();
+        match global.output {
+            OutputFormat::Text => {
+                if traces.is_empty() {
+                    writeln!(stdout, "(no tasks)")?;
+                }
+                for (i, trace) in traces.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(stdout)?;
+                    }
+                    write!(stdout, "{}", trace.waterfall())?;
+                }
+            }
+            OutputFormat::Json => {
+                serde_json::to_writer_pretty(&mut stdout, &traces)?;
+                writeln!(stdout)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Phases of a single task, in the order they started.
+#[derive(Debug, Serialize)]
+struct TaskTrace {
+    task_id: String,
+    target: String,
+    param: Option<String>,
+    status: String,
+    phases: Vec<PhaseRecord>,
+}
+
+impl TaskTrace {
+    /// Renders the phases as bars on a timeline spanning the whole task, with
+    /// the duration and error of each phase alongside.
+    fn waterfall(&self) -> String {
+        let mut out = self.target.clone();
+        if let Some(param) = &self.param {
+            let _ = write!(out, " [{param}]");
+        }
+        let (Some(first), Some(last)) = (
+            self.phases.iter().map(|x| x.started_at).min(),
+            self.phases.iter().map(|x| x.ended_at).max(),
+        ) else {
+            let _ = write!(out, " ({})\n  (no phases)\n", self.status);
+            return out;
+        };
+        let total = (last - first).to_std().unwrap_or_default();
+        let _ = writeln!(
+            out,
+            " ({}, {})",
+            self.status,
+            crate::table::format_duration(total)
+        );
+
+        #[allow(clippy::cast_precision_loss)]
+        let column = |offset: Duration| {
+            if total.is_zero() {
+                return 0.0;
+            }
+            offset.as_secs_f64() / total.as_secs_f64() * TIMELINE_WIDTH as f64
+        };
+        for phase in &self.phases {
+            let start = column((phase.started_at - first).to_std().unwrap_or_default());
+            let end = column((phase.ended_at - first).to_std().unwrap_or_default());
+            let start = to_columns(start.floor()).min(TIMELINE_WIDTH - 1);
+            let end = to_columns(end.ceil());
+            let len = end.saturating_sub(start).max(1);
+            let bar = format!("{}{}", " ".repeat(start), "█".repeat(len));
+            let duration = (phase.ended_at - phase.started_at)
+                .to_std()
+                .unwrap_or_default();
+            let _ = writeln!(
+                out,
+                "  {:<8} |{bar:<TIMELINE_WIDTH$}| {:>9}",
+                phase.name.to_string(),
+                crate::table::format_duration(duration),
+            );
+            if let Some(error) = &phase.error {
+                let _ = writeln!(out, "  {:<8}  ! {error}", "");
+            }
+        }
+        out
+    }
+}
+
+/// Converts a whole number of columns to a count.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+const fn to_columns(columns: f64) -> usize {
+    columns.max(0.0) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use astu_db::PhaseName;
+    use chrono::DateTime;
+    use chrono::TimeDelta;
+    use chrono::Utc;
+
+    use super::*;
+
+    fn phase(name: PhaseName, start_ms: i64, end_ms: i64, error: Option<&str>) -> PhaseRecord {
+        let epoch = DateTime::<Utc>::UNIX_EPOCH;
+        PhaseRecord {
+            task_id: "t1".into(),
+            name,
+            started_at: epoch + TimeDelta::milliseconds(start_ms),
+            ended_at: epoch + TimeDelta::milliseconds(end_ms),
+            error: error.map(Into::into),
+        }
+    }
+
+    fn trace(phases: Vec<PhaseRecord>) -> TaskTrace {
+        TaskTrace {
+            task_id: "t1".into(),
+            target: "ip://10.0.0.1".into(),
+            param: Some("a".into()),
+            status: "failed".into(),
+            phases,
+        }
+    }
+
+    #[test]
+    fn waterfall_offsets_bars() {
+        let trace = trace(vec![
+            phase(PhaseName::Connect, 0, 100, None),
+            phase(PhaseName::Exec, 100, 400, Some("boom")),
+        ]);
+
+        let should = format!(
+            "ip://10.0.0.1 [a] (failed, 400.0ms)\n  connect  |{:<40}|   100.0ms\n  exec     \
+             |{:<40}|   300.0ms\n            ! boom\n",
+            "█".repeat(10),
+            format!("{}{}", " ".repeat(10), "█".repeat(30)),
+        );
+        assert_eq!(trace.waterfall(), should);
+    }
+
+    #[test]
+    fn waterfall_handles_zero_duration() {
+        let trace = trace(vec![
+            phase(PhaseName::Connect, 0, 0, None),
+            phase(PhaseName::Persist, 0, 0, None),
+        ]);
+
+        let should = format!(
+            "ip://10.0.0.1 [a] (failed, 0µs)\n  connect  |{bar:<40}|       0µs\n  persist  \
+             |{bar:<40}|       0µs\n",
+            bar = "█",
+        );
+        assert_eq!(trace.waterfall(), should);
+    }
+
+    #[test]
+    fn waterfall_handles_no_phases() {
+        let trace = trace(vec![]);
+
+        assert_eq!(
+            trace.waterfall(),
+            "ip://10.0.0.1 [a] (failed)\n  (no phases)\n"
+        );
     }
 }
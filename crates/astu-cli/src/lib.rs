@@ -1,6 +1,7 @@
 mod arg;
 mod cmd;
 mod run;
+mod table;
 
 use clap::Parser;
 
@@ -10,7 +11,7 @@ pub use crate::run::Run;
 #[allow(clippy::unused_async)]
 pub async fn run() -> eyre::Result<()> {
     let cli = Cli::parse();
-    cli.command.run().await
+    cli.command.run(&cli.global).await
 }
 
 #[derive(Debug, Parser)]
@@ -1,3 +1,5 @@
+use crate::arg::GlobalFlags;
+
 pub trait Run {
-    async fn run(&self) -> eyre::Result<()>;
+    async fn run(&self, global: &GlobalFlags) -> eyre::Result<()>;
 }
@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+use tabled::Table;
+use tabled::Tabled;
+use tabled::builder::Builder;
+use tabled::settings::Style;
+
+/// Renders rows as a Markdown table, or `(no rows)` if there are none.
+pub fn render<T: Tabled>(rows: impl IntoIterator<Item = T>) -> String {
+    let mut rows = rows.into_iter().peekable();
+    if rows.peek().is_none() {
+        return "(no rows)".to_owned();
+    }
+    Table::new(rows).with(Style::markdown()).to_string()
+}
+
+/// Renders rows with columns only known at runtime, like [`render`].
+///
+/// If any cell spans several lines, rows are separated by lines instead, so
+/// that they can still be told apart.
+pub fn render_columns(header: Vec<String>, rows: Vec<Vec<String>>) -> String {
+    if rows.is_empty() {
+        return "(no rows)".to_owned();
+    }
+    let multiline = rows.iter().flatten().any(|x| x.contains('\n'));
+    let mut builder = Builder::default();
+    builder.push_record(header);
+    for row in rows {
+        builder.push_record(row);
+    }
+    let mut table = builder.build();
+    if multiline {
+        table.with(Style::modern());
+    } else {
+        table.with(Style::markdown());
+    }
+    table.to_string()
+}
+
+/// Formats a duration with a unit suited to its magnitude.
+pub fn format_duration(duration: Duration) -> String {
+    if duration < Duration::from_millis(1) {
+        format!("{}µs", duration.as_micros())
+    } else if duration < Duration::from_secs(1) {
+        format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
+    } else {
+        format!("{:.2}s", duration.as_secs_f64())
+    }
+}
+
+/// Formats a size in bytes with a binary unit suited to its magnitude.
+#[allow(clippy::cast_precision_loss)]
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
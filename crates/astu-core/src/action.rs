@@ -0,0 +1,458 @@
+use std::borrow::Cow;
+use std::ffi::OsStr;
+use std::process::Output;
+use std::sync::Arc;
+use std::time::Duration;
+
+use astu_action::command::Child;
+use astu_action::command::Command;
+use astu_action::command::CommandFactory;
+use astu_action::command::CommandFactoryImpl;
+use astu_action::command::Stdio;
+use astu_action::command::local::LocalCommandFactory;
+use astu_action::command::openssh::OpenSshCommandFactory;
+use astu_action::command::russh::RusshCommand;
+use astu_action::command::russh::RusshCommandFactory;
+use astu_action::transport::Transport;
+use astu_action::transport::TransportFactory;
+use astu_action::transport::TransportFactoryImpl;
+use astu_action::transport::resolve_addr;
+use astu_db::PhaseName;
+use astu_types::Target;
+use astu_types::TargetKind;
+use eyre::Result;
+use eyre::bail;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufReader;
+
+use crate::dedupe::Deduper;
+use crate::spool::Spool;
+use crate::template::Template;
+use crate::template::TemplateToken;
+use crate::trace::Trace;
+
+/// How long to wait for the remote end to send a protocol banner.
+const BANNER_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Banners longer than this are truncated.
+const BANNER_MAX_LEN: usize = 256;
+
+/// Shell used to interpret commands on targets.
+const SHELL: &str = "sh";
+
+/// Sequence of actions performed on each target in a job.
+// Actions are built once per job, so their size does not matter.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone)]
+pub enum Action {
+    /// Connect, then read the protocol banner if the remote end sends one.
+    Ping { transport: TransportFactoryImpl },
+
+    /// Connect, then execute a shell command and wait for it to exit.
+    ///
+    /// Local targets always run as processes on the current machine, while all
+    /// other targets go through the command factory.
+    Run {
+        factory: CommandFactoryImpl,
+        command: Template,
+        /// Stream output lines to the terminal, prefixed with the target, as
+        /// they arrive.
+        live: bool,
+        /// Input fed to the stdin of every command.
+        stdin: Option<Spool>,
+        /// Tokens whose values are replaced by the tokens themselves in the
+        /// captured output.
+        dedupe: Vec<TemplateToken>,
+    },
+}
+
+/// Output captured while performing an action on a single target.
+#[derive(Debug, Clone, Default)]
+pub struct Outcome {
+    pub stdout: Option<String>,
+    pub stderr: Option<String>,
+    pub exitcode: Option<i32>,
+    /// Stdout as captured, if deduplication changed it.
+    pub raw_stdout: Option<String>,
+    /// Stderr as captured, if deduplication changed it.
+    pub raw_stderr: Option<String>,
+}
+
+impl Outcome {
+    /// Deduplicates the output, keeping the raw output if it changed.
+    fn dedupe(self, deduper: &Deduper) -> Self {
+        let dedupe = |output: Option<String>| {
+            output.map_or((None, None), |raw| match deduper.apply(&raw) {
+                Cow::Borrowed(_) => (Some(raw), None),
+                Cow::Owned(output) => (Some(output), Some(raw)),
+            })
+        };
+        let (stdout, raw_stdout) = dedupe(self.stdout);
+        let (stderr, raw_stderr) = dedupe(self.stderr);
+        Self {
+            stdout,
+            stderr,
+            exitcode: self.exitcode,
+            raw_stdout,
+            raw_stderr,
+        }
+    }
+}
+
+impl Action {
+    /// Summary of the action to persist along with the job.
+    #[must_use]
+    pub fn describe(&self) -> serde_json::Value {
+        match self {
+            Self::Ping { .. } => serde_json::json!({ "action": "ping" }),
+            Self::Run { command, .. } => {
+                serde_json::json!({ "action": "run", "command": command.to_string() })
+            }
+        }
+    }
+
+    /// Performs the action on a target, recording each phase. The target and
+    /// param are substituted into the command template.
+    pub async fn perform(
+        &self,
+        target: &Target,
+        param: Option<&str>,
+        trace: &mut Trace,
+    ) -> Result<Outcome> {
+        match self {
+            Self::Ping { transport } => ping(transport, target, trace).await,
+            Self::Run {
+                factory,
+                command,
+                live,
+                stdin,
+                dedupe,
+            } => {
+                let command = &command.render(target, param)?;
+                let stdin = stdin.as_ref();
+                let outcome = if target.kind() == TargetKind::Local {
+                    run(&LocalCommandFactory, target, command, *live, stdin, trace).await
+                } else {
+                    match factory {
+                        CommandFactoryImpl::Mock(f) => {
+                            run(f, target, command, *live, stdin, trace).await
+                        }
+                        CommandFactoryImpl::OpenSsh(f) => {
+                            run_openssh(f, target, command, *live, stdin, trace).await
+                        }
+                        CommandFactoryImpl::Russh(f) => {
+                            run_russh(f, target, command, *live, stdin, trace).await
+                        }
+                    }
+                }?;
+                Ok(outcome.dedupe(&Deduper::new(dedupe, target, param)))
+            }
+        }
+    }
+}
+
+async fn ping(
+    transport: &TransportFactoryImpl,
+    target: &Target,
+    trace: &mut Trace,
+) -> Result<Outcome> {
+    let addr = trace
+        .phase(PhaseName::Resolve, resolve_addr(target))
+        .await?;
+    let transport = trace
+        .phase(PhaseName::Connect, transport.connect(addr))
+        .await?;
+    let banner = trace.phase(PhaseName::Ping, read_banner(transport)).await?;
+    Ok(Outcome {
+        stdout: banner,
+        ..Default::default()
+    })
+}
+
+// Tasks are polled on the engine's own task, so they need not be `Send`.
+#[allow(clippy::future_not_send)]
+async fn run<F: CommandFactory>(
+    factory: &F,
+    target: &Target,
+    command: &str,
+    live: bool,
+    stdin: Option<&Spool>,
+    trace: &mut Trace,
+) -> Result<Outcome> {
+    let cmd = trace
+        .phase(
+            PhaseName::Connect,
+            factory.command(target, OsStr::new(SHELL)),
+        )
+        .await?;
+    exec(cmd, target, command, live, stdin, trace).await
+}
+
+/// Like [`run`], but times resolving the target apart from establishing the
+/// session. The `ssh` binary connects and authenticates in one go, so both are
+/// recorded as authenticating.
+///
+/// Failing to resolve the target is only recorded, since `ssh` resolves it on
+/// its own, possibly through a proxy.
+async fn run_openssh(
+    factory: &OpenSshCommandFactory,
+    target: &Target,
+    command: &str,
+    live: bool,
+    stdin: Option<&Spool>,
+    trace: &mut Trace,
+) -> Result<Outcome> {
+    let _ = trace.phase(PhaseName::Resolve, resolve_addr(target)).await;
+    let cmd = trace
+        .phase(PhaseName::Auth, factory.command(target, OsStr::new(SHELL)))
+        .await?;
+    exec(cmd, target, command, live, stdin, trace).await
+}
+
+/// Like [`run`], but resolves, connects and authenticates in separate phases,
+/// which the native SSH client allows.
+async fn run_russh(
+    factory: &RusshCommandFactory,
+    target: &Target,
+    command: &str,
+    live: bool,
+    stdin: Option<&Spool>,
+    trace: &mut Trace,
+) -> Result<Outcome> {
+    let addr = trace
+        .phase(PhaseName::Resolve, resolve_addr(target))
+        .await?;
+    let mut handle = trace
+        .phase(PhaseName::Connect, factory.connect(target, addr))
+        .await?;
+    trace
+        .phase(PhaseName::Auth, factory.authenticate(&mut handle, target))
+        .await?;
+    let cmd = RusshCommand::new(Arc::new(handle), SHELL);
+    exec(cmd, target, command, live, stdin, trace).await
+}
+
+/// Executes a shell command and waits for it to exit.
+#[allow(clippy::future_not_send)]
+async fn exec<C: Command>(
+    mut cmd: C,
+    target: &Target,
+    command: &str,
+    live: bool,
+    stdin: Option<&Spool>,
+    trace: &mut Trace,
+) -> Result<Outcome> {
+    cmd.arg("-c")
+        .arg(command)
+        .stdin(if stdin.is_some() {
+            Stdio::Piped
+        } else {
+            Stdio::Null
+        })
+        .stdout(Stdio::Piped)
+        .stderr(Stdio::Piped);
+    let mut child = trace.phase(PhaseName::Exec, cmd.spawn()).await?;
+    let feed = feed_stdin(stdin, child.take_stdin());
+    let wait = async {
+        if live {
+            wait_live(child, target).await
+        } else {
+            child.wait_with_output().await
+        }
+    };
+    let ((), output) = trace
+        .phase(PhaseName::Wait, async { Ok(tokio::join!(feed, wait)) })
+        .await?;
+    let output = output?;
+    Ok(Outcome {
+        stdout: Some(String::from_utf8_lossy(&output.stdout).into_owned()),
+        stderr: Some(String::from_utf8_lossy(&output.stderr).into_owned()),
+        exitcode: output.status.code(),
+        ..Default::default()
+    })
+}
+
+/// Copies spooled input to the stdin of a command, then closes it. Commands
+/// are free to exit without reading all of their input.
+async fn feed_stdin(spool: Option<&Spool>, stdin: Option<impl AsyncWrite + Unpin>) {
+    let (Some(spool), Some(mut stdin)) = (spool, stdin) else {
+        return;
+    };
+    if let Err(error) = spool.copy_to(&mut stdin).await {
+        tracing::debug!("stopped feeding stdin: {error:#}");
+    }
+    let _ = stdin.shutdown().await;
+}
+
+/// Like [`Child::wait_with_output`], but also prints each line of output to
+/// the terminal as it arrives, prefixed with the target.
+#[allow(clippy::future_not_send)]
+async fn wait_live<C: Child>(mut child: C, target: &Target) -> Result<Output> {
+    let stdout = tee_lines(child.take_stdout(), |line| println!("{target} | {line}"));
+    let stderr = tee_lines(child.take_stderr(), |line| eprintln!("{target} | {line}"));
+    let (stdout, stderr, status) = tokio::try_join!(stdout, stderr, child.wait())?;
+    Ok(Output {
+        status,
+        stdout,
+        stderr,
+    })
+}
+
+/// Reads a stream to the end, passing each line to a callback.
+async fn tee_lines<R: AsyncRead + Unpin>(
+    reader: Option<R>,
+    print: impl Fn(&str),
+) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    let Some(reader) = reader else {
+        return Ok(buf);
+    };
+    let mut reader = BufReader::new(reader);
+    loop {
+        let start = buf.len();
+        if reader.read_until(b'\n', &mut buf).await? == 0 {
+            return Ok(buf);
+        }
+        let line = String::from_utf8_lossy(&buf[start..]);
+        print(line.trim_end_matches(['\n', '\r']));
+    }
+}
+
+/// Reads the first line sent by the remote end, such as the SSH protocol
+/// version. Remote ends that stay silent are not an error.
+async fn read_banner(transport: Transport) -> Result<Option<String>> {
+    let Transport::Tcp(mut stream) = transport else {
+        bail!("unsupported transport: {transport:?}");
+    };
+
+    let mut buf = [0u8; BANNER_MAX_LEN];
+    let mut len = 0;
+    let read = async {
+        while len < buf.len() {
+            let n = stream.read(&mut buf[len..]).await?;
+            len += n;
+            if n == 0 || buf[..len].contains(&b'\n') {
+                break;
+            }
+        }
+        Ok::<_, std::io::Error>(())
+    };
+    if let Ok(result) = tokio::time::timeout(BANNER_TIMEOUT, read).await {
+        result?;
+    }
+
+    let banner = String::from_utf8_lossy(&buf[..len]);
+    let line = banner.lines().next().unwrap_or_default().trim();
+    Ok(Some(line.to_owned()).filter(|x| !x.is_empty()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use astu_action::command::mock::MockCommandFactory;
+    use astu_action::command::mock::MockScript;
+    use astu_action::transport::tcp;
+    use rstest::rstest;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    async fn serve(banner: &'static [u8]) -> Result<Target> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await?;
+            stream.write_all(banner).await?;
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok::<_, std::io::Error>(())
+        });
+        Target::try_from(addr)
+    }
+
+    #[tokio::test]
+    async fn ping_reads_banner() -> Result<()> {
+        let target = serve(b"SSH-2.0-OpenSSH_9.6\r\nmore").await?;
+        let transport = TransportFactoryImpl::Tcp(tcp::TransportFactory::new(BANNER_TIMEOUT));
+        let action = Action::Ping { transport };
+        let mut trace = Trace::new("t1".into());
+
+        let outcome = action.perform(&target, None, &mut trace).await?;
+
+        assert_eq!(outcome.stdout.as_deref(), Some("SSH-2.0-OpenSSH_9.6"));
+        let phases: Vec<_> = trace.into_phases().iter().map(|x| x.name).collect();
+        assert_eq!(
+            phases,
+            vec![PhaseName::Resolve, PhaseName::Connect, PhaseName::Ping]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn ping_allows_silence() -> Result<()> {
+        let target = serve(b"").await?;
+        let transport = TransportFactoryImpl::Tcp(tcp::TransportFactory::new(BANNER_TIMEOUT));
+        let action = Action::Ping { transport };
+        let mut trace = Trace::new("t1".into());
+
+        let outcome = action.perform(&target, None, &mut trace).await?;
+
+        assert_eq!(outcome.stdout, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn ping_records_connect_error() -> Result<()> {
+        let target = Target::from_str("ip://127.0.0.1:1")?;
+        let transport = TransportFactoryImpl::Tcp(tcp::TransportFactory::new(BANNER_TIMEOUT));
+        let action = Action::Ping { transport };
+        let mut trace = Trace::new("t1".into());
+
+        assert!(action.perform(&target, None, &mut trace).await.is_err());
+
+        let phases = trace.into_phases();
+        assert_eq!(phases.len(), 2);
+        assert_eq!(phases[1].name, PhaseName::Connect);
+        assert!(phases[1].error.is_some());
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(false)]
+    #[case(true)]
+    #[tokio::test]
+    async fn run_captures_output(#[case] live: bool) -> Result<()> {
+        let target = Target::from_str("10.0.0.1")?;
+        let factory = MockCommandFactory::default().with_default(MockScript {
+            stdout: b"one\ntwo\nthree".to_vec(),
+            stderr: b"warn\n".to_vec(),
+            exitcode: 1,
+            ..Default::default()
+        });
+        let action = Action::Run {
+            factory: CommandFactoryImpl::Mock(factory),
+            command: Template::from_str("true")?,
+            live,
+            stdin: None,
+            dedupe: vec![],
+        };
+        let mut trace = Trace::new("t1".into());
+
+        let outcome = action.perform(&target, None, &mut trace).await?;
+
+        assert_eq!(outcome.stdout.as_deref(), Some("one\ntwo\nthree"));
+        assert_eq!(outcome.stderr.as_deref(), Some("warn\n"));
+        assert_eq!(outcome.exitcode, Some(1));
+        let phases: Vec<_> = trace.into_phases().iter().map(|x| x.name).collect();
+        assert_eq!(
+            phases,
+            vec![PhaseName::Connect, PhaseName::Exec, PhaseName::Wait]
+        );
+        Ok(())
+    }
+}
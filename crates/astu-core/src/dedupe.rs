@@ -0,0 +1,127 @@
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::fmt::Write;
+
+use astu_types::Target;
+
+use crate::template::TemplateToken;
+
+/// Replaces the values of template tokens for a task with the tokens
+/// themselves, so that output differing only by those values is identical
+/// across tasks.
+#[derive(Debug, Clone, Default)]
+pub struct Deduper {
+    /// Values and their tokens, longest value first.
+    replacements: Vec<(String, TemplateToken)>,
+}
+
+/// Constructors
+impl Deduper {
+    /// Creates a deduper for the values of some tokens for a task. Tokens
+    /// without a value for the task are skipped.
+    ///
+    /// Hosts with a domain name also match their short hostname. Values shared
+    /// by several tokens, such as the host and IP of an IP target, are
+    /// replaced by the first of them.
+    #[must_use]
+    pub fn new(tokens: &[TemplateToken], target: &Target, param: Option<&str>) -> Self {
+        let mut replacements = Vec::new();
+        for &token in tokens {
+            let Some(value) = token.value(target, param) else {
+                continue;
+            };
+            if token == TemplateToken::Host
+                && target.domain().is_some()
+                && let Some((short, _)) = value.split_once('.')
+            {
+                replacements.push((short.to_owned(), token));
+            }
+            replacements.push((value, token));
+        }
+        replacements.retain(|(value, _)| !value.is_empty());
+        // Longest first, so that e.g. FQDNs win over short hostnames. The sort
+        // is stable, so ties keep the order of the tokens.
+        replacements.sort_by_key(|(value, _)| Reverse(value.len()));
+        Self { replacements }
+    }
+}
+
+impl Deduper {
+    /// Replaces each whole-word occurrence of a value with its token, taking
+    /// the longest value at each position.
+    #[must_use]
+    pub fn apply<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut out = String::new();
+        let mut copied = 0;
+        let mut i = 0;
+        while i < text.len() {
+            let rest = &text[i..];
+            let found = self.replacements.iter().find(|(value, _)| {
+                rest.starts_with(value.as_str()) && is_whole_word(text, i, i + value.len())
+            });
+            if let Some((value, token)) = found {
+                out.push_str(&text[copied..i]);
+                let _ = write!(out, "{{{token}}}");
+                i += value.len();
+                copied = i;
+            } else {
+                i += rest.chars().next().map_or(1, char::len_utf8);
+            }
+        }
+        if copied == 0 {
+            return Cow::Borrowed(text);
+        }
+        out.push_str(&text[copied..]);
+        Cow::Owned(out)
+    }
+}
+
+/// Whether a match does not continue a word on either side, so that e.g.
+/// `10.0.0.1` does not match within `10.0.0.12`.
+fn is_whole_word(text: &str, start: usize, end: usize) -> bool {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let edge = |inner: Option<char>, outer: Option<char>| {
+        !(inner.is_some_and(is_word) && outer.is_some_and(is_word))
+    };
+    let value = &text[start..end];
+    edge(value.chars().next(), text[..start].chars().next_back())
+        && edge(value.chars().next_back(), text[end..].chars().next())
+}
+
+#[cfg(test)]
+// Tokens use the same braces as format strings.
+#[allow(clippy::literal_string_with_formatting_args)]
+mod tests {
+    use std::str::FromStr;
+
+    use rstest::rstest;
+
+    use super::*;
+
+    const TOKENS: [TemplateToken; 4] = [
+        TemplateToken::Param,
+        TemplateToken::Host,
+        TemplateToken::User,
+        TemplateToken::Ip,
+    ];
+
+    #[rustfmt::skip::attributes(case)]
+    #[rstest]
+    #[case("dns://root@web1.example.com", None,        "web1.example.com up", "{host} up")]
+    #[case("dns://root@web1.example.com", None,        "web1 up as root",     "{host} up as {user}")]
+    #[case("dns://root@web1.example.com", None,        "web10 /root/x",       "web10 /{user}/x")]
+    #[case("ip://10.0.0.1",               None,        "10.0.0.1 10.0.0.12",  "{host} 10.0.0.12")]
+    #[case("ip://10.0.0.1",               Some("/v1"), "GET /v1/x",           "GET {param}/x")]
+    #[case("ip://10.0.0.1",               None,        "nothing",             "nothing")]
+    fn apply_works(
+        #[case] target: &str,
+        #[case] param: Option<&str>,
+        #[case] text: &str,
+        #[case] should: &str,
+    ) -> eyre::Result<()> {
+        let target = Target::from_str(target)?;
+        let deduper = Deduper::new(&TOKENS, &target, param);
+        assert_eq!(deduper.apply(text), should);
+        Ok(())
+    }
+}
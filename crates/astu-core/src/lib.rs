@@ -1,24 +1,72 @@
+mod action;
+mod dedupe;
 mod id;
+mod schedule;
+mod spool;
+mod template;
+mod trace;
 mod util;
 
 use std::collections::BTreeSet;
+use std::time::Duration;
 
+use astu_db::Db;
+use astu_db::JobRecord;
+use astu_db::ResultRecord;
+use astu_db::TaskRecord;
+use astu_db::TaskStatus;
 use astu_resolve::ChainResolver;
 use astu_resolve::ResolveExt;
 use astu_types::Target;
 use bon::Builder;
+use chrono::Utc;
+use eyre::Result;
+use eyre::eyre;
+use futures::Stream;
+use futures::StreamExt;
+use futures::stream::FuturesUnordered;
+use tokio::time::Instant;
 
+pub use crate::action::Action;
+pub use crate::action::Outcome;
+pub use crate::dedupe::Deduper;
 pub use crate::id::Id;
 pub use crate::id::IdGenerator;
 pub use crate::id::IdGeneratorImpl;
+pub use crate::id::SonyflakeGenerator;
+pub use crate::schedule::GroupLimit;
+use crate::schedule::Scheduler;
+pub use crate::schedule::TaskGroup;
+pub use crate::spool::Spool;
+pub use crate::template::Template;
+pub use crate::template::TemplateToken;
+pub use crate::trace::Trace;
+pub use crate::trace::error_string;
 pub use crate::util::AstuTryFutureExt;
 pub use crate::util::AstuTryStreamExt;
 
+/// Number of tasks that may run at once if not otherwise set.
+pub const DEFAULT_CONCURRENCY: usize = 256;
+
 #[derive(Builder)]
 pub struct Engine {
     id_generator: IdGeneratorImpl,
     forward_resolver: ChainResolver,
     reverse_resolver: ChainResolver,
+
+    /// Maximum number of tasks running at once.
+    #[builder(default = DEFAULT_CONCURRENCY)]
+    concurrency: usize,
+
+    /// Per-task timeout. Tasks are not timed out if unset.
+    timeout: Option<Duration>,
+
+    /// Maximum number of tasks started per second. Starts are not limited if
+    /// unset.
+    rate: Option<f64>,
+
+    /// Maximum number of tasks running at once within each group of targets.
+    group_limit: Option<GroupLimit>,
 }
 
 impl Engine {
@@ -43,25 +91,733 @@ impl Engine {
         JobPlan {
             id,
             targets: resolved,
+            params: None,
         }
     }
+
+    /// Persists a job plan as the latest job, with a pending task for each
+    /// target and param.
+    ///
+    /// # Errors
+    ///
+    /// If persisting to the database fails.
+    pub async fn persist_plan(
+        &self,
+        db: &Db,
+        plan: &JobPlan,
+        action: &Action,
+        cmdline: Vec<String>,
+    ) -> Result<()> {
+        let job_id = plan.id.to_string();
+        let job = JobRecord {
+            id: job_id.clone(),
+            started_at: Utc::now(),
+            cmdline,
+            plan: action.describe(),
+        };
+        let tasks: Vec<_> = plan
+            .tasks()
+            .into_iter()
+            .map(|(target, param)| TaskRecord {
+                id: self.id_generator.id_now().to_string(),
+                job_id: job_id.clone(),
+                target: target.clone(),
+                param: param.map(ToOwned::to_owned),
+                status: TaskStatus::Pending,
+            })
+            .collect();
+
+        db.insert_job(&job).await?;
+        db.insert_tasks(&tasks).await?;
+        db.set_latest_job(&job_id).await?;
+        Ok(())
+    }
+
+    /// Performs an action on each pending task in a job, bounded by the
+    /// concurrency limit. Results are persisted as each task finishes.
+    ///
+    /// # Errors
+    ///
+    /// If persisting to the database fails. Errors from the action itself are
+    /// persisted as task results instead.
+    pub async fn execute(&self, db: &Db, job_id: &str, action: &Action) -> Result<()> {
+        self.execute_until(db, job_id, action, futures::stream::pending())
+            .await
+            .map(drop)
+    }
+
+    /// Like [`Engine::execute`], but stops in two stages on interrupts. Returns
+    /// the number of tasks that were canceled.
+    ///
+    /// After the first interrupt, no more tasks start, but running tasks are
+    /// waited for. Tasks that did not start are then persisted as canceled,
+    /// so that the job may be resumed. After a second interrupt, running tasks
+    /// are dropped and persisted as canceled too.
+    ///
+    /// # Errors
+    ///
+    /// If persisting to the database fails. Errors from the action itself are
+    /// persisted as task results instead.
+    pub async fn execute_until(
+        &self,
+        db: &Db,
+        job_id: &str,
+        action: &Action,
+        interrupts: impl Stream<Item = ()>,
+    ) -> Result<u64> {
+        let tasks = db
+            .tasks(job_id)
+            .await?
+            .into_iter()
+            .filter(|task| task.status == TaskStatus::Pending)
+            .collect();
+        let mut scheduler = Scheduler::new(tasks, self.rate, self.group_limit);
+        let mut interrupts = std::pin::pin!(interrupts);
+        let mut running = FuturesUnordered::new();
+        let mut interrupted = false;
+        let concurrency = self.concurrency.max(1);
+        loop {
+            let now = Instant::now();
+            while !interrupted && running.len() < concurrency {
+                let Some((task, key)) = scheduler.next(now) else {
+                    break;
+                };
+                running.push(async move { (key, self.execute_task(db, action, task).await) });
+            }
+            // Only wake for the next start if it is still ahead and there is
+            // room for it, otherwise the wait would end at once.
+            let wake_at = scheduler
+                .wake_at()
+                .filter(|&x| !interrupted && running.len() < concurrency && x > now);
+            if running.is_empty() && wake_at.is_none() {
+                break;
+            }
+            tokio::select! {
+                Some((key, result)) = running.next() => {
+                    result?;
+                    scheduler.release(key.as_deref());
+                }
+                Some(()) = interrupts.next() => {
+                    if interrupted {
+                        drop(running);
+                        let from = [TaskStatus::Pending, TaskStatus::Running];
+                        return db.set_job_task_status(job_id, &from, TaskStatus::Canceled).await;
+                    }
+                    interrupted = true;
+                }
+                () = tokio::time::sleep_until(wake_at.unwrap_or_else(Instant::now)), if wake_at.is_some() => {}
+            }
+        }
+        if !interrupted {
+            return Ok(0);
+        }
+        db.set_job_task_status(job_id, &[TaskStatus::Pending], TaskStatus::Canceled)
+            .await
+    }
+
+    async fn execute_task(&self, db: &Db, action: &Action, task: TaskRecord) -> Result<()> {
+        db.set_task_status(&task.id, TaskStatus::Running).await?;
+
+        let mut trace = Trace::new(task.id.clone());
+        let param = task.param.as_deref();
+        let outcome = match self.timeout {
+            None => action.perform(&task.target, param, &mut trace).await,
+            Some(duration) => {
+                let future = action.perform(&task.target, param, &mut trace);
+                match tokio::time::timeout(duration, future).await {
+                    Ok(outcome) => outcome,
+                    Err(_elapsed) => {
+                        let error = eyre!("task timed out after {duration:?}");
+                        trace.interrupt(&error);
+                        Err(error)
+                    }
+                }
+            }
+        };
+
+        let (status, result) = match outcome {
+            Ok(outcome) => (
+                TaskStatus::Complete,
+                ResultRecord {
+                    task_id: task.id,
+                    stdout: outcome.stdout,
+                    stderr: outcome.stderr,
+                    exitcode: outcome.exitcode,
+                    error: None,
+                    raw_stdout: outcome.raw_stdout,
+                    raw_stderr: outcome.raw_stderr,
+                },
+            ),
+            Err(error) => (
+                TaskStatus::Failed,
+                ResultRecord {
+                    task_id: task.id,
+                    error: Some(error_string(&error)),
+                    ..Default::default()
+                },
+            ),
+        };
+        db.finish_task(status, &result, &trace.into_phases()).await
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct JobPlan {
     pub id: Id,
     pub targets: BTreeSet<Target>,
+    /// Parameters that each target fans out over, if any.
+    pub params: Option<Vec<String>>,
 }
 
-#[derive(Debug, Clone)]
-pub struct Task {
-    pub target: Target,
-    pub actions: Vec<Action>,
+impl JobPlan {
+    /// Fans out each target over parameters, so that there is a task for
+    /// every pair of target and param.
+    #[must_use]
+    pub fn with_params(mut self, params: Vec<String>) -> Self {
+        self.params = Some(params);
+        self
+    }
+
+    /// Number of tasks in the plan.
+    #[must_use]
+    pub fn task_count(&self) -> usize {
+        let fanout = self.params.as_ref().map_or(1, Vec::len);
+        self.targets.len() * fanout
+    }
+
+    /// Target and param of each task in the plan.
+    #[must_use]
+    pub fn tasks(&self) -> Vec<(&Target, Option<&str>)> {
+        let params: Vec<_> = self.params.as_ref().map_or_else(
+            || vec![None],
+            |params| params.iter().map(|x| Some(x.as_str())).collect(),
+        );
+        self.targets
+            .iter()
+            .flat_map(|target| params.iter().map(move |param| (target, *param)))
+            .collect()
+    }
 }
 
-#[derive(Debug, Clone)]
-pub enum Action {
-    Connect,
-    Ping,
-    RunCommand { command: String },
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use astu_action::command::CommandFactoryImpl;
+    use astu_action::command::mock::MockCommandFactory;
+    use astu_action::command::mock::MockScript;
+    use astu_action::command::openssh::OpenSshCommandFactory;
+    use astu_action::transport::TransportFactoryImpl;
+    use astu_action::transport::tcp;
+    use astu_db::Field;
+    use astu_db::PhaseName;
+    use tokio::net::TcpListener;
+    use tokio::signal::unix::SignalKind;
+    use tokio::signal::unix::signal;
+
+    use super::*;
+
+    fn engine() -> Result<Engine> {
+        engine_with_timeout(Duration::from_secs(5))
+    }
+
+    fn engine_with_timeout(timeout: Duration) -> Result<Engine> {
+        let engine = Engine::builder()
+            .id_generator(SonyflakeGenerator::from_hostname()?.into())
+            .forward_resolver(ChainResolver::default())
+            .reverse_resolver(ChainResolver::default())
+            .timeout(timeout)
+            .build();
+        Ok(engine)
+    }
+
+    #[tokio::test]
+    async fn execute_persists_results() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let open = Target::try_from(listener.local_addr()?)?;
+        let closed = Target::from_str("127.0.0.1:1")?;
+
+        let db = Db::open_in_memory().await?;
+        let engine = engine()?;
+        let transport =
+            TransportFactoryImpl::Tcp(tcp::TransportFactory::new(Duration::from_secs(1)));
+        let action = Action::Ping { transport };
+        let plan = engine.job_plan([open.clone(), closed.clone()]).await;
+        let job_id = plan.id.to_string();
+
+        engine.persist_plan(&db, &plan, &action, vec![]).await?;
+        assert_eq!(db.latest_job().await?.as_deref(), Some(job_id.as_str()));
+
+        engine.execute(&db, &job_id, &action).await?;
+
+        let tasks = db.tasks(&job_id).await?;
+        let status = |target: &Target| tasks.iter().find(|x| &x.target == target).map(|x| x.status);
+        assert_eq!(status(&open), Some(TaskStatus::Complete));
+        assert_eq!(status(&closed), Some(TaskStatus::Failed));
+        assert_eq!(db.freq(&job_id, Field::Error).await?.len(), 1);
+        // Resolve, connect and ping, then resolve and a failed connect, plus
+        // persisting each task
+        assert_eq!(db.phases(&job_id).await?.len(), 7);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn execute_traces_openssh_phases() -> Result<()> {
+        let closed = TcpListener::bind("127.0.0.1:0").await?.local_addr()?;
+        let db = Db::open_in_memory().await?;
+        let engine = engine()?;
+        let factory =
+            CommandFactoryImpl::OpenSsh(OpenSshCommandFactory::new(Duration::from_secs(1)));
+        let action = Action::Run {
+            factory,
+            command: Template::from_str("true")?,
+            live: false,
+            stdin: None,
+            dedupe: vec![],
+        };
+        let plan = engine
+            .job_plan([Target::from_str(&format!("ssh://{closed}"))?])
+            .await;
+        let job_id = plan.id.to_string();
+
+        engine.persist_plan(&db, &plan, &action, vec![]).await?;
+        engine.execute(&db, &job_id, &action).await?;
+
+        let phases = db.phases(&job_id).await?;
+        let names: Vec<_> = phases.iter().map(|x| x.name).collect();
+        assert_eq!(
+            names,
+            vec![PhaseName::Resolve, PhaseName::Auth, PhaseName::Persist]
+        );
+        assert_eq!(phases[0].error, None);
+        assert!(phases[1].error.is_some());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn execute_runs_local_commands() -> Result<()> {
+        let db = Db::open_in_memory().await?;
+        let engine = engine()?;
+        let factory =
+            CommandFactoryImpl::OpenSsh(OpenSshCommandFactory::new(Duration::from_secs(1)));
+        let action = Action::Run {
+            factory,
+            command: Template::from_str("echo hello; echo oops >&2; exit 7")?,
+            live: false,
+            stdin: None,
+            dedupe: vec![],
+        };
+        let plan = engine.job_plan([Target::new_local()?]).await;
+        let job_id = plan.id.to_string();
+
+        engine.persist_plan(&db, &plan, &action, vec![]).await?;
+        engine.execute(&db, &job_id, &action).await?;
+
+        let results = db.results(&job_id).await?;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].stdout.as_deref(), Some("hello\n"));
+        assert_eq!(results[0].stderr.as_deref(), Some("oops\n"));
+        assert_eq!(results[0].exitcode, Some(7));
+        assert_eq!(results[0].error, None);
+        let phases: Vec<_> = db.phases(&job_id).await?.iter().map(|x| x.name).collect();
+        assert_eq!(
+            phases,
+            vec![
+                PhaseName::Connect,
+                PhaseName::Exec,
+                PhaseName::Wait,
+                PhaseName::Persist
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn execute_handles_scripted_failures() -> Result<()> {
+        let ok = Target::from_str("10.0.0.1")?;
+        let exit = Target::from_str("10.0.0.2")?;
+        let spawn = Target::from_str("10.0.0.3")?;
+        let hang = Target::from_str("10.0.0.4")?;
+        let factory = MockCommandFactory::default()
+            .with(
+                ok.clone(),
+                MockScript {
+                    stdout: b"ok\n".to_vec(),
+                    delay: Duration::from_millis(10),
+                    ..Default::default()
+                },
+            )
+            .with(
+                exit.clone(),
+                MockScript {
+                    stderr: b"no\n".to_vec(),
+                    exitcode: 2,
+                    ..Default::default()
+                },
+            )
+            .with(
+                spawn.clone(),
+                MockScript {
+                    spawn_error: Some("exec failed".into()),
+                    ..Default::default()
+                },
+            )
+            .with(
+                hang.clone(),
+                MockScript {
+                    hang: true,
+                    ..Default::default()
+                },
+            );
+        let action = Action::Run {
+            factory: CommandFactoryImpl::Mock(factory.clone()),
+            command: Template::from_str("true")?,
+            live: false,
+            stdin: None,
+            dedupe: vec![],
+        };
+
+        let db = Db::open_in_memory().await?;
+        let engine = engine_with_timeout(Duration::from_millis(200))?;
+        let plan = engine
+            .job_plan([ok.clone(), exit.clone(), spawn.clone(), hang.clone()])
+            .await;
+        let job_id = plan.id.to_string();
+        engine.persist_plan(&db, &plan, &action, vec![]).await?;
+        engine.execute(&db, &job_id, &action).await?;
+
+        let tasks = db.tasks(&job_id).await?;
+        let results = db.results(&job_id).await?;
+        let result = |target: &Target| {
+            let task = tasks.iter().find(|x| &x.target == target)?;
+            let result = results.iter().find(|x| x.task_id == task.id)?;
+            Some((task.status, result.clone()))
+        };
+
+        let (status, ok) = result(&ok).ok_or_else(|| eyre::eyre!("no result"))?;
+        assert_eq!(status, TaskStatus::Complete);
+        assert_eq!(ok.stdout.as_deref(), Some("ok\n"));
+        assert_eq!(ok.exitcode, Some(0));
+
+        let (status, exit) = result(&exit).ok_or_else(|| eyre::eyre!("no result"))?;
+        assert_eq!(status, TaskStatus::Complete);
+        assert_eq!(exit.stderr.as_deref(), Some("no\n"));
+        assert_eq!(exit.exitcode, Some(2));
+
+        let (status, spawn) = result(&spawn).ok_or_else(|| eyre::eyre!("no result"))?;
+        assert_eq!(status, TaskStatus::Failed);
+        assert_eq!(spawn.error.as_deref(), Some("exec failed"));
+
+        let (status, hang) = result(&hang).ok_or_else(|| eyre::eyre!("no result"))?;
+        assert_eq!(status, TaskStatus::Failed);
+        assert!(hang.error.is_some_and(|x| x.contains("timed out")));
+
+        assert_eq!(factory.invocations().len(), 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn execute_feeds_spooled_stdin() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let spool = Spool::create(dir.path().join("spool"), &b"patch\n"[..]).await?;
+        let factory = MockCommandFactory::default();
+        let action = Action::Run {
+            factory: CommandFactoryImpl::Mock(factory.clone()),
+            command: Template::from_str("patch -p1")?,
+            live: false,
+            stdin: Some(spool),
+            dedupe: vec![],
+        };
+        let targets = [Target::from_str("10.0.0.1")?, Target::from_str("10.0.0.2")?];
+
+        let db = Db::open_in_memory().await?;
+        let engine = engine()?;
+        let plan = engine.job_plan(targets).await;
+        let job_id = plan.id.to_string();
+        engine.persist_plan(&db, &plan, &action, vec![]).await?;
+        engine.execute(&db, &job_id, &action).await?;
+
+        let invocations = factory.invocations();
+        assert_eq!(invocations.len(), 2);
+        for invocation in invocations {
+            assert_eq!(invocation.stdin, b"patch\n");
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn execute_fans_out_params() -> Result<()> {
+        let factory = MockCommandFactory::default();
+        let action = Action::Run {
+            factory: CommandFactoryImpl::Mock(factory.clone()),
+            command: Template::from_str("curl {param}")?,
+            live: false,
+            stdin: None,
+            dedupe: vec![],
+        };
+        let targets = [Target::from_str("10.0.0.1")?, Target::from_str("10.0.0.2")?];
+        let params = vec!["a".into(), "b".into(), "c".into()];
+
+        let db = Db::open_in_memory().await?;
+        let engine = engine()?;
+        let plan = engine.job_plan(targets).await.with_params(params);
+        assert_eq!(plan.task_count(), 6);
+        let job_id = plan.id.to_string();
+        engine.persist_plan(&db, &plan, &action, vec![]).await?;
+        engine.execute(&db, &job_id, &action).await?;
+
+        let tasks = db.tasks(&job_id).await?;
+        let params: Vec<_> = tasks.iter().filter_map(|x| x.param.as_deref()).collect();
+        assert_eq!(params, ["a", "b", "c", "a", "b", "c"]);
+        let mut commands: Vec<_> = factory
+            .invocations()
+            .into_iter()
+            .map(|x| (x.target.to_string(), x.args[1].clone()))
+            .collect();
+        commands.sort();
+        assert_eq!(commands.len(), 6);
+        assert_eq!(commands[0], ("ip://10.0.0.1".into(), "curl a".into()));
+        assert_eq!(commands[5], ("ip://10.0.0.2".into(), "curl c".into()));
+        Ok(())
+    }
+
+    /// Persists a job that runs `sleep` locally once per param, one at a
+    /// time.
+    async fn sleep_job(db: &Db, params: &[&str]) -> Result<(Engine, Action, String)> {
+        let engine = Engine::builder()
+            .id_generator(SonyflakeGenerator::from_hostname()?.into())
+            .forward_resolver(ChainResolver::default())
+            .reverse_resolver(ChainResolver::default())
+            .concurrency(1)
+            .build();
+        let factory =
+            CommandFactoryImpl::OpenSsh(OpenSshCommandFactory::new(Duration::from_secs(1)));
+        let action = Action::Run {
+            factory,
+            command: Template::from_str("sleep 0.3; echo {param}")?,
+            live: false,
+            stdin: None,
+            dedupe: vec![],
+        };
+        let params = params.iter().map(ToString::to_string).collect();
+        let plan = engine
+            .job_plan([Target::new_local()?])
+            .await
+            .with_params(params);
+        let job_id = plan.id.to_string();
+        engine.persist_plan(db, &plan, &action, vec![]).await?;
+        Ok((engine, action, job_id))
+    }
+
+    fn statuses(tasks: &[TaskRecord]) -> Vec<TaskStatus> {
+        tasks.iter().map(|x| x.status).collect()
+    }
+
+    #[tokio::test]
+    async fn execute_until_waits_for_running_tasks() -> Result<()> {
+        let db = Db::open_in_memory().await?;
+        let (engine, action, job_id) = sleep_job(&db, &["a", "b", "c"]).await?;
+        let (tx, rx) = futures::channel::mpsc::unbounded();
+        let interrupt = async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            tx.unbounded_send(())
+        };
+
+        let (canceled, sent) =
+            tokio::join!(engine.execute_until(&db, &job_id, &action, rx), interrupt);
+        sent?;
+        assert_eq!(canceled?, 2);
+        assert_eq!(
+            statuses(&db.tasks(&job_id).await?),
+            [
+                TaskStatus::Complete,
+                TaskStatus::Canceled,
+                TaskStatus::Canceled
+            ]
+        );
+        assert_eq!(db.results(&job_id).await?[0].stdout.as_deref(), Some("a\n"));
+
+        // Resuming runs only the canceled tasks.
+        db.set_job_task_status(&job_id, &[TaskStatus::Canceled], TaskStatus::Pending)
+            .await?;
+        engine.execute(&db, &job_id, &action).await?;
+        assert_eq!(
+            statuses(&db.tasks(&job_id).await?),
+            [
+                TaskStatus::Complete,
+                TaskStatus::Complete,
+                TaskStatus::Complete
+            ]
+        );
+        assert_eq!(db.phases(&job_id).await?.len(), 12);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn execute_until_shields_tasks_from_terminal_interrupts() -> Result<()> {
+        // Leave the process group shared with cargo, which a Ctrl-C in the
+        // terminal would otherwise stop too.
+        // SAFETY: Only moves this process into a new group of its own.
+        assert_eq!(unsafe { libc::setpgid(0, 0) }, 0);
+        let mut sigint = signal(SignalKind::interrupt())?;
+        let interrupts = futures::stream::poll_fn(move |cx| sigint.poll_recv(cx));
+        let db = Db::open_in_memory().await?;
+        let (engine, action, job_id) = sleep_job(&db, &["a"]).await?;
+        let interrupt = async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            // SAFETY: Sends SIGINT to the process group, as a terminal does.
+            unsafe { libc::kill(0, libc::SIGINT) }
+        };
+
+        let (canceled, sent) = tokio::join!(
+            engine.execute_until(&db, &job_id, &action, interrupts),
+            interrupt
+        );
+        assert_eq!(sent, 0);
+        assert_eq!(canceled?, 0);
+        assert_eq!(statuses(&db.tasks(&job_id).await?), [TaskStatus::Complete]);
+        let results = db.results(&job_id).await?;
+        assert_eq!(results[0].exitcode, Some(0));
+        assert_eq!(results[0].stdout.as_deref(), Some("a\n"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn execute_until_drops_running_tasks() -> Result<()> {
+        let db = Db::open_in_memory().await?;
+        let (engine, action, job_id) = sleep_job(&db, &["a", "b"]).await?;
+        let (tx, rx) = futures::channel::mpsc::unbounded();
+        tx.unbounded_send(())?;
+        tx.unbounded_send(())?;
+
+        let canceled = engine.execute_until(&db, &job_id, &action, rx).await?;
+        assert_eq!(canceled, 2);
+        assert_eq!(
+            statuses(&db.tasks(&job_id).await?),
+            [TaskStatus::Canceled, TaskStatus::Canceled]
+        );
+        assert!(db.results(&job_id).await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn execute_limits_start_rate() -> Result<()> {
+        let action = Action::Run {
+            factory: CommandFactoryImpl::Mock(MockCommandFactory::default()),
+            command: Template::from_str("true")?,
+            live: false,
+            stdin: None,
+            dedupe: vec![],
+        };
+        let targets = ["10.0.0.1", "10.0.0.2", "10.0.0.3"]
+            .into_iter()
+            .map(Target::from_str)
+            .collect::<Result<Vec<_>>>()?;
+        let engine = Engine::builder()
+            .id_generator(SonyflakeGenerator::from_hostname()?.into())
+            .forward_resolver(ChainResolver::default())
+            .reverse_resolver(ChainResolver::default())
+            .rate(20.0)
+            .build();
+
+        let db = Db::open_in_memory().await?;
+        let plan = engine.job_plan(targets).await;
+        let job_id = plan.id.to_string();
+        engine.persist_plan(&db, &plan, &action, vec![]).await?;
+        let started = std::time::Instant::now();
+        engine.execute(&db, &job_id, &action).await?;
+
+        // Starts are 50ms apart.
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        assert_eq!(db.freq(&job_id, Field::Status).await?.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn execute_waits_for_running_tasks_when_rate_limited() -> Result<()> {
+        let targets = [Target::from_str("10.0.0.1")?, Target::from_str("10.0.0.2")?];
+        let mut factory = MockCommandFactory::default();
+        for target in &targets {
+            let script = MockScript {
+                delay: Duration::from_millis(200),
+                ..Default::default()
+            };
+            factory = factory.with(target.clone(), script);
+        }
+        let action = Action::Run {
+            factory: CommandFactoryImpl::Mock(factory),
+            command: Template::from_str("true")?,
+            live: false,
+            stdin: None,
+            dedupe: vec![],
+        };
+        let engine = Engine::builder()
+            .id_generator(SonyflakeGenerator::from_hostname()?.into())
+            .forward_resolver(ChainResolver::default())
+            .reverse_resolver(ChainResolver::default())
+            .concurrency(1)
+            .rate(100.0)
+            .build();
+
+        let db = Db::open_in_memory().await?;
+        let plan = engine.job_plan(targets).await;
+        let job_id = plan.id.to_string();
+        engine.persist_plan(&db, &plan, &action, vec![]).await?;
+        // Counts how often the loop is polled, as it polls the interrupts each
+        // time. Running tasks wake it a few dozen times, while spinning on a
+        // past wake time polls it tens of thousands of times.
+        let polls = std::sync::atomic::AtomicUsize::new(0);
+        let interrupts = futures::stream::poll_fn(|_| {
+            polls.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            std::task::Poll::Pending
+        });
+        engine
+            .execute_until(&db, &job_id, &action, interrupts)
+            .await?;
+
+        assert_eq!(db.freq(&job_id, Field::Status).await?.len(), 1);
+        let polls = polls.into_inner();
+        assert!(polls < 1000, "loop was polled {polls} times");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn execute_dedupes_output() -> Result<()> {
+        let targets = [Target::from_str("10.0.0.1")?, Target::from_str("10.0.0.2")?];
+        let mut factory = MockCommandFactory::default();
+        for target in &targets {
+            let script = MockScript {
+                stdout: format!("{} ok\n", target.ip().ok_or_else(|| eyre!("no ip"))?).into(),
+                ..Default::default()
+            };
+            factory = factory.with(target.clone(), script);
+        }
+        let action = Action::Run {
+            factory: CommandFactoryImpl::Mock(factory),
+            command: Template::from_str("hostname -i")?,
+            live: false,
+            stdin: None,
+            dedupe: vec![TemplateToken::Ip],
+        };
+
+        let db = Db::open_in_memory().await?;
+        let engine = engine()?;
+        let plan = engine.job_plan(targets).await;
+        let job_id = plan.id.to_string();
+        engine.persist_plan(&db, &plan, &action, vec![]).await?;
+        engine.execute(&db, &job_id, &action).await?;
+
+        let freq = db.freq(&job_id, Field::Stdout).await?;
+        assert_eq!(freq.len(), 1);
+        assert_eq!(freq[0].value, "{ip} ok\n");
+        assert_eq!(freq[0].count, 2);
+        let raw: Vec<_> = db
+            .results(&job_id)
+            .await?
+            .into_iter()
+            .filter_map(|x| x.raw_stdout)
+            .collect();
+        assert_eq!(raw, ["10.0.0.1 ok\n", "10.0.0.2 ok\n"]);
+        Ok(())
+    }
 }
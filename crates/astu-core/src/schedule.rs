@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use astu_db::TaskRecord;
+use astu_types::Target;
+use eyre::Result;
+use eyre::WrapErr;
+use eyre::bail;
+use ipnet::IpNet;
+use tokio::time::Instant;
+
+/// How targets are grouped for per-group concurrency limits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskGroup {
+    /// Targets with IP addresses in the same network of this prefix length,
+    /// written as `/24`. Longer prefixes than an address has are clamped, so
+    /// `/64` groups IPv4 addresses by themselves.
+    Network(u8),
+    /// Targets with hostnames in the same parent domain, written as `domain`.
+    /// A hostname without a parent domain is its own group.
+    Domain,
+}
+
+impl TaskGroup {
+    /// Key of the group a target is in, if it is in one. Targets without a
+    /// group are not limited.
+    #[must_use]
+    pub fn key(self, target: &Target) -> Option<String> {
+        match self {
+            Self::Network(prefix_len) => {
+                let ip = target.ip()?;
+                let max_prefix_len = if ip.is_ipv4() { 32 } else { 128 };
+                let net = IpNet::new(ip, prefix_len.min(max_prefix_len)).ok()?;
+                Some(net.trunc().to_string())
+            }
+            Self::Domain => {
+                let domain = target.domain()?;
+                let parent = domain.split_once('.').map_or(domain, |(_, parent)| parent);
+                Some(parent.to_ascii_lowercase())
+            }
+        }
+    }
+}
+
+impl FromStr for TaskGroup {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s == "domain" {
+            return Ok(Self::Domain);
+        }
+        let Some(prefix_len) = s.strip_prefix('/') else {
+            bail!("invalid task group `{s}`; expected a prefix length like `/24`, or `domain`");
+        };
+        let prefix_len = prefix_len
+            .parse()
+            .wrap_err_with(|| format!("invalid prefix length in task group `{s}`"))?;
+        if prefix_len > 128 {
+            bail!("invalid prefix length in task group `{s}`; must be at most 128");
+        }
+        Ok(Self::Network(prefix_len))
+    }
+}
+
+impl fmt::Display for TaskGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Network(prefix_len) => write!(f, "/{prefix_len}"),
+            Self::Domain => f.write_str("domain"),
+        }
+    }
+}
+
+/// Maximum number of tasks running at once within each group of targets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GroupLimit {
+    pub group: TaskGroup,
+    pub concurrency: usize,
+}
+
+/// Decides which pending task starts next, and when.
+///
+/// Tasks start in order, except that a task whose group is full waits for a
+/// task of its group to finish while later tasks start. Starts are spaced
+/// evenly if there is a rate limit.
+#[derive(Debug)]
+pub struct Scheduler {
+    queue: VecDeque<TaskRecord>,
+    /// Tasks whose group has room again, ahead of the queue.
+    ready: VecDeque<TaskRecord>,
+    /// Tasks waiting for a task of their group to finish.
+    blocked: HashMap<String, VecDeque<TaskRecord>>,
+    running: HashMap<String, usize>,
+    group_limit: Option<GroupLimit>,
+    /// Time between starts, if starts are rate limited.
+    interval: Option<Duration>,
+    next_start: Option<Instant>,
+}
+
+/// Constructors
+impl Scheduler {
+    pub fn new(tasks: Vec<TaskRecord>, rate: Option<f64>, group_limit: Option<GroupLimit>) -> Self {
+        let interval = rate
+            .filter(|x| x.is_finite() && *x > 0.0)
+            .and_then(|x| Duration::try_from_secs_f64(1.0 / x).ok());
+        Self {
+            queue: tasks.into(),
+            ready: VecDeque::new(),
+            blocked: HashMap::new(),
+            running: HashMap::new(),
+            group_limit,
+            interval,
+            next_start: None,
+        }
+    }
+}
+
+impl Scheduler {
+    /// Next task that may start now, along with its group key. Its group
+    /// counts it as running until it is [released](Self::release).
+    pub fn next(&mut self, now: Instant) -> Option<(TaskRecord, Option<String>)> {
+        if self.next_start.is_some_and(|x| now < x) {
+            return None;
+        }
+        loop {
+            let task = self.ready.pop_front().or_else(|| self.queue.pop_front())?;
+            let key = self.key(&task);
+            if let (Some(key), Some(limit)) = (&key, self.group_limit) {
+                let running = self.running.entry(key.clone()).or_default();
+                if *running >= limit.concurrency.max(1) {
+                    self.blocked.entry(key.clone()).or_default().push_back(task);
+                    continue;
+                }
+                *running += 1;
+            }
+            if let Some(interval) = self.interval {
+                self.next_start = Some(self.next_start.map_or(now, |x| x.max(now)) + interval);
+            }
+            return Some((task, key));
+        }
+    }
+
+    /// Frees the place of a finished task in its group, letting a task that
+    /// was waiting for it start next.
+    pub fn release(&mut self, key: Option<&str>) {
+        let Some(key) = key else {
+            return;
+        };
+        if let Some(running) = self.running.get_mut(key) {
+            *running = running.saturating_sub(1);
+        }
+        if let Some(task) = self.blocked.get_mut(key).and_then(VecDeque::pop_front) {
+            self.ready.push_back(task);
+        }
+    }
+
+    /// When the next task may start, if starts are rate limited and a task is
+    /// waiting to start.
+    pub fn wake_at(&self) -> Option<Instant> {
+        if self.ready.is_empty() && self.queue.is_empty() {
+            return None;
+        }
+        self.next_start
+    }
+
+    fn key(&self, task: &TaskRecord) -> Option<String> {
+        self.group_limit?.group.key(&task.target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use astu_db::TaskStatus;
+    use rstest::rstest;
+
+    use super::*;
+
+    fn tasks(targets: &[&str]) -> Result<Vec<TaskRecord>> {
+        targets
+            .iter()
+            .enumerate()
+            .map(|(i, target)| {
+                Ok(TaskRecord {
+                    id: format!("t{i}"),
+                    job_id: "j1".into(),
+                    target: Target::from_str(target)?,
+                    param: None,
+                    status: TaskStatus::Pending,
+                })
+            })
+            .collect()
+    }
+
+    #[rustfmt::skip::attributes(case)]
+    #[rstest]
+    #[case("/24",    "10.0.1.7",            Some("10.0.1.0/24"))]
+    #[case("/64",    "10.0.1.7",            Some("10.0.1.7/32"))]
+    #[case("/64",    "[2001:db8::1]:22",    Some("2001:db8::/64"))]
+    #[case("/24",    "web1.example.com",    None)]
+    #[case("domain", "web1.Example.com",    Some("example.com"))]
+    #[case("domain", "localhost",           Some("localhost"))]
+    #[case("domain", "10.0.1.7",            None)]
+    fn key_works(
+        #[case] group: &str,
+        #[case] target: &str,
+        #[case] should: Option<&str>,
+    ) -> Result<()> {
+        let group = TaskGroup::from_str(group)?;
+        let target = Target::from_str(target)?;
+        assert_eq!(group.key(&target).as_deref(), should);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("/24")]
+    #[case("domain")]
+    fn group_display_roundtrips(#[case] group: &str) -> Result<()> {
+        assert_eq!(TaskGroup::from_str(group)?.to_string(), group);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("24")]
+    #[case("/129")]
+    #[case("host")]
+    fn group_rejects_invalid(#[case] group: &str) {
+        assert!(TaskGroup::from_str(group).is_err());
+    }
+
+    #[tokio::test]
+    async fn next_limits_groups() -> Result<()> {
+        let tasks = tasks(&["10.0.0.1", "10.0.0.2", "10.0.0.3", "10.0.1.1", "web1"])?;
+        let group_limit = GroupLimit {
+            group: TaskGroup::Network(24),
+            concurrency: 2,
+        };
+        let mut scheduler = Scheduler::new(tasks, None, Some(group_limit));
+        let now = Instant::now();
+        let mut next = || scheduler.next(now).map(|(task, _)| task.id);
+
+        // The third task of 10.0.0.0/24 waits, while later tasks start.
+        assert_eq!(next().as_deref(), Some("t0"));
+        assert_eq!(next().as_deref(), Some("t1"));
+        assert_eq!(next().as_deref(), Some("t3"));
+        assert_eq!(next().as_deref(), Some("t4"));
+        assert_eq!(next(), None);
+
+        scheduler.release(Some("10.0.0.0/24"));
+        assert_eq!(
+            scheduler.next(now).map(|(task, _)| task.id).as_deref(),
+            Some("t2")
+        );
+        assert_eq!(scheduler.next(now).map(|(task, _)| task.id), None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn next_limits_rate() -> Result<()> {
+        let tasks = tasks(&["10.0.0.1", "10.0.0.2", "10.0.0.3"])?;
+        let mut scheduler = Scheduler::new(tasks, Some(10.0), None);
+        let now = Instant::now();
+
+        assert!(scheduler.next(now).is_some());
+        assert!(scheduler.next(now).is_none());
+        let wake_at = scheduler.wake_at();
+        assert_eq!(wake_at, Some(now + Duration::from_millis(100)));
+
+        let later = now + Duration::from_millis(100);
+        assert!(scheduler.next(later).is_some());
+        assert!(scheduler.next(later + Duration::from_millis(100)).is_some());
+        assert_eq!(scheduler.wake_at(), None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn new_ignores_unrepresentable_rate() -> Result<()> {
+        let tasks = tasks(&["10.0.0.1", "10.0.0.2"])?;
+        let mut scheduler = Scheduler::new(tasks, Some(1e-20), None);
+        let now = Instant::now();
+
+        assert!(scheduler.next(now).is_some());
+        assert!(scheduler.next(now).is_some());
+        Ok(())
+    }
+}
@@ -0,0 +1,181 @@
+use std::io::SeekFrom;
+use std::path::Path;
+use std::path::PathBuf;
+
+use eyre::Result;
+use eyre::WrapErr;
+use tokio::fs::File;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncSeekExt;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::watch;
+
+/// Size of chunks copied in and out of the spool file.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Input written once to a file and then read by any number of tasks, each
+/// with its own cursor.
+///
+/// Readers may start before the input is fully spooled; they follow the file
+/// until the input ends, so tasks started late still receive all of it.
+#[derive(Debug, Clone)]
+pub struct Spool {
+    path: PathBuf,
+    state: watch::Receiver<SpoolState>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct SpoolState {
+    /// Bytes written to the file so far.
+    len: u64,
+    /// Whether the input has ended.
+    done: bool,
+}
+
+/// Constructors
+impl Spool {
+    /// Spools input into a new file in the background.
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be created.
+    pub async fn create(
+        path: impl Into<PathBuf>,
+        input: impl AsyncRead + Unpin + Send + 'static,
+    ) -> Result<Self> {
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .wrap_err_with(|| format!("failed to create spool dir {}", parent.display()))?;
+        }
+        let file = File::create(&path)
+            .await
+            .wrap_err_with(|| format!("failed to create spool file {}", path.display()))?;
+
+        let (tx, rx) = watch::channel(SpoolState::default());
+        tokio::spawn(async move {
+            if let Err(error) = fill(file, input, &tx).await {
+                tracing::warn!("stopped spooling input: {error:#}");
+            }
+            tx.send_modify(|x| x.done = true);
+        });
+        Ok(Self { path, state: rx })
+    }
+
+    /// Opens a spool file whose input has already ended.
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be read.
+    pub async fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let metadata = tokio::fs::metadata(&path)
+            .await
+            .wrap_err_with(|| format!("failed to open spool file {}", path.display()))?;
+        let state = SpoolState {
+            len: metadata.len(),
+            done: true,
+        };
+        let (_, rx) = watch::channel(state);
+        Ok(Self { path, state: rx })
+    }
+}
+
+/// Accessors
+impl Spool {
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Spool {
+    /// Copies the entire input to a writer from the start, waiting for more
+    /// input until it ends. Returns the number of bytes copied.
+    ///
+    /// # Errors
+    ///
+    /// If reading the spool file or writing to the writer fails.
+    pub async fn copy_to(&self, writer: &mut (impl AsyncWrite + Unpin)) -> Result<u64> {
+        let mut file = File::open(&self.path).await?;
+        let mut state = self.state.clone();
+        let mut cursor = 0;
+        let mut buf = vec![0; CHUNK_SIZE];
+        loop {
+            let SpoolState { len, done } = *state.borrow_and_update();
+            if cursor < len {
+                file.seek(SeekFrom::Start(cursor)).await?;
+                let want = usize::try_from(len - cursor)
+                    .unwrap_or(CHUNK_SIZE)
+                    .min(CHUNK_SIZE);
+                let n = file.read(&mut buf[..want]).await?;
+                writer.write_all(&buf[..n]).await?;
+                cursor += n as u64;
+            } else if done {
+                writer.flush().await?;
+                return Ok(cursor);
+            } else if state.changed().await.is_err() {
+                // The spooler went away without marking the input as ended.
+                return Ok(cursor);
+            }
+        }
+    }
+}
+
+async fn fill(
+    mut file: File,
+    mut input: impl AsyncRead + Unpin,
+    tx: &watch::Sender<SpoolState>,
+) -> Result<()> {
+    let mut buf = vec![0; CHUNK_SIZE];
+    loop {
+        let n = input.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        file.write_all(&buf[..n]).await?;
+        file.flush().await?;
+        tx.send_modify(|x| x.len += n as u64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn copy_to_follows_input() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let (mut input, rx) = tokio::io::duplex(16);
+        let spool = Spool::create(dir.path().join("spool/job"), rx).await?;
+
+        let early = {
+            let spool = spool.clone();
+            tokio::spawn(async move {
+                let mut out = Vec::new();
+                spool.copy_to(&mut out).await?;
+                Ok::<_, eyre::Report>(out)
+            })
+        };
+        input.write_all(b"hello ").await?;
+        input.write_all(b"world").await?;
+        drop(input);
+
+        assert_eq!(early.await??, b"hello world");
+
+        let mut late = Vec::new();
+        spool.copy_to(&mut late).await?;
+        assert_eq!(late, b"hello world");
+
+        let mut resumed = Vec::new();
+        Spool::open(spool.path())
+            .await?
+            .copy_to(&mut resumed)
+            .await?;
+        assert_eq!(resumed, b"hello world");
+        Ok(())
+    }
+}
@@ -0,0 +1,251 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use astu_action::command::quote;
+use astu_types::Host;
+use astu_types::Target;
+use eyre::Result;
+use eyre::bail;
+use eyre::eyre;
+use strum::Display;
+use strum::EnumString;
+
+/// Per-task values that may be substituted into a command template.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Display, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum TemplateToken {
+    /// Param of the task, if the job fans out over params.
+    Param,
+    /// Target hostname, which may be an IP address.
+    Host,
+    /// Target login username.
+    User,
+    /// Target IP address.
+    Ip,
+    /// Target port.
+    Port,
+    /// Target kind, such as `ssh`.
+    Kind,
+    /// Target URI.
+    Target,
+}
+
+impl TemplateToken {
+    /// Unquoted value of the token for a task, if it has one.
+    #[must_use]
+    pub fn value(self, target: &Target, param: Option<&str>) -> Option<String> {
+        match self {
+            Self::Param => param.map(ToOwned::to_owned),
+            Self::Host => target.host().map(|host| match host {
+                Host::Ip(ip) => ip.to_string(),
+                Host::Domain(domain) => domain,
+            }),
+            Self::User => target.user().map(Cow::into_owned),
+            Self::Ip => target.ip().map(|x| x.to_string()),
+            Self::Port => target.port().map(|x| x.to_string()),
+            Self::Kind => Some(target.kind().to_string()),
+            Self::Target => Some(target.to_string()),
+        }
+    }
+}
+
+/// Shell command with `{token}` placeholders that are substituted per task.
+///
+/// Literal braces are written as `{{` and `}}`. Substituted values are quoted
+/// for the shell, so each one is always a single word.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Token(TemplateToken),
+}
+
+impl FromStr for Template {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '{' if chars.next_if(|x| x.1 == '{').is_some() => literal.push('{'),
+                '}' if chars.next_if(|x| x.1 == '}').is_some() => literal.push('}'),
+                '{' => {
+                    let rest = &s[i + 1..];
+                    let Some(len) = rest.find('}') else {
+                        bail!("unclosed `{{` at offset {i}; use `{{{{` for a literal brace");
+                    };
+                    let name = &rest[..len];
+                    let token = TemplateToken::from_str(name).map_err(|_| {
+                        eyre!("unknown template token `{{{name}}}`; use `{{{{` for a literal brace")
+                    })?;
+                    while chars.next_if(|x| x.0 <= i + 1 + len).is_some() {}
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Token(token));
+                }
+                '}' => bail!("unmatched `}}` at offset {i}; use `}}}}` for a literal brace"),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(Self {
+            source: s.to_owned(),
+            segments,
+        })
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Template {
+    /// Tokens used in the template, in order of appearance.
+    pub fn tokens(&self) -> impl Iterator<Item = TemplateToken> + '_ {
+        self.segments.iter().filter_map(|x| match x {
+            Segment::Literal(_) => None,
+            Segment::Token(token) => Some(*token),
+        })
+    }
+
+    /// Whether a token is used in the template.
+    #[must_use]
+    pub fn uses(&self, token: TemplateToken) -> bool {
+        self.tokens().any(|x| x == token)
+    }
+
+    /// Substitutes the values of a task into the template.
+    ///
+    /// # Errors
+    ///
+    /// If a token has no value for the task.
+    pub fn render(&self, target: &Target, param: Option<&str>) -> Result<String> {
+        let mut command = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => command.push_str(literal),
+                Segment::Token(token) => {
+                    let value = token
+                        .value(target, param)
+                        .ok_or_else(|| eyre!("template token `{{{token}}}` is unavailable"))?;
+                    command.push_str(&quote(&value));
+                }
+            }
+        }
+        Ok(command)
+    }
+
+    /// Checks that every token has a value for every task, so that a job can
+    /// fail before any of its tasks start.
+    ///
+    /// # Errors
+    ///
+    /// If any token has no value for some task.
+    pub fn validate<'a>(
+        &self,
+        tasks: impl IntoIterator<Item = (&'a Target, Option<&'a str>)>,
+    ) -> Result<()> {
+        let mut missing: BTreeMap<TemplateToken, (usize, &Target)> = BTreeMap::new();
+        for (target, param) in tasks {
+            for token in self.tokens() {
+                if token.value(target, param).is_none() {
+                    missing.entry(token).or_insert((0, target)).0 += 1;
+                }
+            }
+        }
+        if missing.is_empty() {
+            return Ok(());
+        }
+        let reasons: Vec<_> = missing
+            .into_iter()
+            .map(|(token, (count, target))| {
+                format!("`{{{token}}}` is unavailable for {count} tasks, such as {target}")
+            })
+            .collect();
+        bail!("invalid command template: {}", reasons.join("; "))
+    }
+}
+
+#[cfg(test)]
+// Templates use the same braces as format strings.
+#[allow(clippy::literal_string_with_formatting_args)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rustfmt::skip::attributes(case)]
+    #[rstest]
+    #[case("echo {host}",                       "ssh://root@example.com:22", None,         "echo example.com")]
+    #[case("echo {user}@{ip}:{port}",           "ip://root@10.0.0.1:22",     None,         "echo root@10.0.0.1:22")]
+    #[case("echo {kind} {target}",              "10.0.0.1",                  None,         "echo ip ip://10.0.0.1")]
+    #[case("curl {param}",                      "local:",                    Some("a b"),  "curl 'a b'")]
+    #[case("echo {param}",                      "local:",                    Some("it's"), r"echo 'it'\''s'")]
+    #[case("echo {param}",                      "local:",                    Some(""),     "echo ''")]
+    #[case("awk '{{print $1}}' {{}} {{{host}}}", "10.0.0.1",                  None,         "awk '{print $1}' {} {10.0.0.1}")]
+    fn render_works(
+        #[case] template: &str,
+        #[case] target: &str,
+        #[case] param: Option<&str>,
+        #[case] should: &str,
+    ) -> Result<()> {
+        let template = Template::from_str(template)?;
+        let target = Target::from_str(target)?;
+        assert_eq!(template.render(&target, param)?, should);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("echo {", "unclosed `{` at offset 5")]
+    #[case("echo }", "unmatched `}` at offset 5")]
+    #[case("echo ${HOME}", "unknown template token `{HOME}`")]
+    fn parse_rejects_invalid(#[case] template: &str, #[case] should: &str) {
+        let error = Template::from_str(template)
+            .map(drop)
+            .map_err(|x| x.to_string());
+        assert!(error.is_err_and(|x| x.starts_with(should)));
+    }
+
+    #[test]
+    fn display_roundtrips() -> Result<()> {
+        let source = "printf '{{}}' {param}";
+        assert_eq!(Template::from_str(source)?.to_string(), source);
+        Ok(())
+    }
+
+    #[test]
+    fn validate_reports_missing_tokens() -> Result<()> {
+        let template = Template::from_str("ping {ip}")?;
+        let ip = Target::from_str("10.0.0.1")?;
+        let dns = Target::from_str("example.com")?;
+        let local = Target::new_local()?;
+
+        assert!(template.validate([(&ip, None)]).is_ok());
+        let error = template
+            .validate([(&ip, None), (&dns, None), (&local, None)])
+            .map_err(|x| x.to_string());
+        assert_eq!(
+            error,
+            Err(
+                "invalid command template: `{ip}` is unavailable for 2 tasks, such as \
+                 dns://example.com"
+                    .into()
+            )
+        );
+        Ok(())
+    }
+}
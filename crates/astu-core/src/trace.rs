@@ -0,0 +1,68 @@
+use std::future::Future;
+
+use astu_db::PhaseName;
+use astu_db::PhaseRecord;
+use chrono::DateTime;
+use chrono::Utc;
+use eyre::Result;
+
+/// Records the timing of each phase a task goes through.
+#[derive(Debug)]
+pub struct Trace {
+    task_id: String,
+    phases: Vec<PhaseRecord>,
+    current: Option<(PhaseName, DateTime<Utc>)>,
+}
+
+impl Trace {
+    #[must_use]
+    pub const fn new(task_id: String) -> Self {
+        Self {
+            task_id,
+            phases: Vec::new(),
+            current: None,
+        }
+    }
+
+    /// Awaits a future as a phase, recording its timing and error if any.
+    pub async fn phase<T>(
+        &mut self,
+        name: PhaseName,
+        future: impl Future<Output = Result<T>>,
+    ) -> Result<T> {
+        self.current = Some((name, Utc::now()));
+        let result = future.await;
+        self.end(result.as_ref().err().map(error_string));
+        result
+    }
+
+    /// Ends the phase in progress, if any, with an error. This is for when the
+    /// phase future was dropped before completing, such as on timeout.
+    pub fn interrupt(&mut self, error: &eyre::Report) {
+        self.end(Some(error_string(error)));
+    }
+
+    #[must_use]
+    pub fn into_phases(self) -> Vec<PhaseRecord> {
+        self.phases
+    }
+
+    fn end(&mut self, error: Option<String>) {
+        let Some((name, started_at)) = self.current.take() else {
+            return;
+        };
+        self.phases.push(PhaseRecord {
+            task_id: self.task_id.clone(),
+            name,
+            started_at,
+            ended_at: Utc::now(),
+            error,
+        });
+    }
+}
+
+/// Formats an error along with its chain of causes on a single line.
+#[must_use]
+pub fn error_string(error: &eyre::Report) -> String {
+    format!("{error:#}")
+}
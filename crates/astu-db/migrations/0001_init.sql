@@ -0,0 +1,43 @@
+-- A job is a single invocation of an action subcommand.
+CREATE TABLE job (
+    id         TEXT PRIMARY KEY NOT NULL,
+    started_at TEXT NOT NULL,
+    cmdline    TEXT NOT NULL,
+    plan       TEXT NOT NULL
+);
+
+-- A task is the unit of work performed on a single target within a job.
+CREATE TABLE task (
+    id     TEXT PRIMARY KEY NOT NULL,
+    job_id TEXT NOT NULL REFERENCES job (id) ON DELETE CASCADE,
+    target TEXT NOT NULL,
+    status TEXT NOT NULL
+);
+
+CREATE INDEX task_job_id ON task (job_id);
+
+-- Captured output of a task. At most one row per task.
+CREATE TABLE result (
+    task_id  TEXT PRIMARY KEY NOT NULL REFERENCES task (id) ON DELETE CASCADE,
+    stdout   TEXT,
+    stderr   TEXT,
+    exitcode INTEGER,
+    error    TEXT
+);
+
+-- Timing of each phase a task went through.
+CREATE TABLE phase (
+    task_id    TEXT NOT NULL REFERENCES task (id) ON DELETE CASCADE,
+    name       TEXT NOT NULL,
+    started_at TEXT NOT NULL,
+    ended_at   TEXT NOT NULL,
+    error      TEXT
+);
+
+CREATE INDEX phase_task_id ON phase (task_id);
+
+-- Key-value store for global state, such as the latest job ID.
+CREATE TABLE meta (
+    key   TEXT PRIMARY KEY NOT NULL,
+    value TEXT NOT NULL
+);
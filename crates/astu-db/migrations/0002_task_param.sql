@@ -0,0 +1,3 @@
+-- Parameter substituted for `{param}` in the command template, if the job
+-- fans out over parameters read from stdin.
+ALTER TABLE task ADD COLUMN param TEXT;
@@ -0,0 +1,3 @@
+-- Output as captured, kept only if deduplication changed it.
+ALTER TABLE result ADD COLUMN raw_stdout TEXT;
+ALTER TABLE result ADD COLUMN raw_stderr TEXT;
@@ -0,0 +1,939 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use astu_types::Target;
+use chrono::Utc;
+use eyre::Result;
+use eyre::WrapErr;
+use sqlx::Row;
+use sqlx::SqliteExecutor;
+use sqlx::SqlitePool;
+use sqlx::migrate::Migrator;
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::sqlite::SqliteJournalMode;
+use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::sqlite::SqliteRow;
+use sqlx::types::Json;
+
+use crate::Field;
+use crate::FreqRecord;
+use crate::JobRecord;
+use crate::JobSummary;
+use crate::PhaseName;
+use crate::PhaseRecord;
+use crate::ResultRecord;
+use crate::TaskRecord;
+use crate::TaskStatus;
+
+static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// File name of the database within the data directory.
+const DB_FILE_NAME: &str = "astu.db";
+
+/// Key in the `meta` table holding the latest action job ID.
+const LATEST_JOB_KEY: &str = "latest_job";
+
+/// Handle to the Astu database. Cheap to clone.
+#[derive(Debug, Clone)]
+pub struct Db {
+    pool: SqlitePool,
+}
+
+/// Constructors
+impl Db {
+    /// Opens the database in the given data directory, creating both if they
+    /// do not exist. Schema migrations are always run.
+    ///
+    /// # Errors
+    ///
+    /// - If the data directory cannot be created
+    /// - If the database cannot be opened
+    /// - If migrations fail
+    pub async fn open(data_dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(data_dir)
+            .wrap_err_with(|| format!("failed to create data directory {}", data_dir.display()))?;
+        let options = SqliteConnectOptions::new()
+            .filename(data_dir.join(DB_FILE_NAME))
+            .create_if_missing(true)
+            .foreign_keys(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .busy_timeout(Duration::from_secs(30));
+        let pool = SqlitePoolOptions::new()
+            .connect_with(options)
+            .await
+            .wrap_err("failed to open database")?;
+        Self::migrate(pool).await
+    }
+
+    /// Opens a private in-memory database. Schema migrations are always run.
+    ///
+    /// # Errors
+    ///
+    /// - If the database cannot be opened
+    /// - If migrations fail
+    pub async fn open_in_memory() -> Result<Self> {
+        let options = SqliteConnectOptions::from_str("sqlite::memory:")?.foreign_keys(true);
+        // Each connection to an in-memory database is its own database
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options)
+            .await
+            .wrap_err("failed to open in-memory database")?;
+        Self::migrate(pool).await
+    }
+
+    async fn migrate(pool: SqlitePool) -> Result<Self> {
+        MIGRATOR
+            .run(&pool)
+            .await
+            .wrap_err("failed to migrate database")?;
+        Ok(Self { pool })
+    }
+}
+
+/// Jobs
+impl Db {
+    /// # Errors
+    ///
+    /// If the query fails, such as when the job ID already exists.
+    pub async fn insert_job(&self, job: &JobRecord) -> Result<()> {
+        sqlx::query("INSERT INTO job (id, started_at, cmdline, plan) VALUES (?, ?, ?, ?)")
+            .bind(&job.id)
+            .bind(job.started_at)
+            .bind(Json(&job.cmdline))
+            .bind(Json(&job.plan))
+            .execute(&self.pool)
+            .await
+            .wrap_err_with(|| format!("failed to insert job {}", job.id))?;
+        Ok(())
+    }
+
+    /// # Errors
+    ///
+    /// If the query fails.
+    pub async fn job(&self, job_id: &str) -> Result<Option<JobRecord>> {
+        let row = sqlx::query("SELECT id, started_at, cmdline, plan FROM job WHERE id = ?")
+            .bind(job_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        row.as_ref().map(job_from_row).transpose()
+    }
+
+    /// All jobs, oldest first.
+    ///
+    /// # Errors
+    ///
+    /// If the query fails.
+    pub async fn jobs(&self) -> Result<Vec<JobRecord>> {
+        let rows = sqlx::query("SELECT id, started_at, cmdline, plan FROM job ORDER BY started_at")
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(job_from_row).collect()
+    }
+
+    /// All jobs with the number of their tasks in each status, oldest first.
+    ///
+    /// # Errors
+    ///
+    /// If the query fails.
+    pub async fn job_summaries(&self) -> Result<Vec<JobSummary>> {
+        let rows = sqlx::query(
+            "SELECT job_id, status, COUNT(*) AS count FROM task GROUP BY job_id, status",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        let mut counts: HashMap<String, Vec<(TaskStatus, u64)>> = HashMap::new();
+        for row in &rows {
+            let status: String = row.try_get("status")?;
+            let status = TaskStatus::from_str(&status)
+                .wrap_err_with(|| format!("unknown task status: {status}"))?;
+            let count: i64 = row.try_get("count")?;
+            counts
+                .entry(row.try_get("job_id")?)
+                .or_default()
+                .push((status, count.try_into()?));
+        }
+        let summaries = self
+            .jobs()
+            .await?
+            .into_iter()
+            .map(|job| JobSummary {
+                task_counts: counts
+                    .remove(&job.id)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .collect(),
+                job,
+            })
+            .collect();
+        Ok(summaries)
+    }
+
+    /// # Errors
+    ///
+    /// If the query fails.
+    pub async fn set_latest_job(&self, job_id: &str) -> Result<()> {
+        sqlx::query("INSERT OR REPLACE INTO meta (key, value) VALUES (?, ?)")
+            .bind(LATEST_JOB_KEY)
+            .bind(job_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// ID of the latest action job, if one has run.
+    ///
+    /// # Errors
+    ///
+    /// If the query fails.
+    pub async fn latest_job(&self) -> Result<Option<String>> {
+        let value = sqlx::query_scalar("SELECT value FROM meta WHERE key = ?")
+            .bind(LATEST_JOB_KEY)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(value)
+    }
+
+    /// Returns the given job ID if it exists, otherwise the latest job ID.
+    ///
+    /// # Errors
+    ///
+    /// - If the given job does not exist
+    /// - If no job ID was given and no job has run yet
+    pub async fn job_or_latest(&self, job_id: Option<&str>) -> Result<String> {
+        let job_id = match job_id {
+            Some(job_id) => job_id.to_owned(),
+            None => self
+                .latest_job()
+                .await?
+                .ok_or_else(|| eyre::eyre!("no jobs found"))?,
+        };
+        if self.job(&job_id).await?.is_none() {
+            eyre::bail!("job not found: {job_id}");
+        }
+        Ok(job_id)
+    }
+}
+
+/// Tasks
+impl Db {
+    /// Inserts many tasks in a single transaction.
+    ///
+    /// # Errors
+    ///
+    /// If the query fails, such as when a task ID already exists.
+    pub async fn insert_tasks(&self, tasks: impl IntoIterator<Item = &TaskRecord>) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for task in tasks {
+            sqlx::query(
+                "INSERT INTO task (id, job_id, target, param, status) VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(&task.id)
+            .bind(&task.job_id)
+            .bind(task.target.to_string())
+            .bind(&task.param)
+            .bind(task.status.to_string())
+            .execute(&mut *tx)
+            .await
+            .wrap_err_with(|| format!("failed to insert task {}", task.id))?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// # Errors
+    ///
+    /// If the query fails.
+    pub async fn set_task_status(&self, task_id: &str, status: TaskStatus) -> Result<()> {
+        update_task_status(&self.pool, task_id, status).await
+    }
+
+    /// Moves every task of a job in any of some statuses to another status.
+    /// Returns the number of tasks moved.
+    ///
+    /// # Errors
+    ///
+    /// If the query fails.
+    pub async fn set_job_task_status(
+        &self,
+        job_id: &str,
+        from: &[TaskStatus],
+        to: TaskStatus,
+    ) -> Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        let mut count = 0;
+        for status in from {
+            count += sqlx::query("UPDATE task SET status = ? WHERE job_id = ? AND status = ?")
+                .bind(to.to_string())
+                .bind(job_id)
+                .bind(status.to_string())
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+        }
+        tx.commit()
+            .await
+            .wrap_err_with(|| format!("failed to update tasks of job {job_id}"))?;
+        Ok(count)
+    }
+
+    /// Number of tasks in a job.
+    ///
+    /// # Errors
+    ///
+    /// If the query fails.
+    pub async fn task_count(&self, job_id: &str) -> Result<u64> {
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM task WHERE job_id = ?")
+            .bind(job_id)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count.try_into()?)
+    }
+
+    /// Persists everything observed while running a task in a single
+    /// transaction: its final status, its result and the timing of its phases.
+    /// A `persist` phase timing these writes is recorded too.
+    ///
+    /// # Errors
+    ///
+    /// If the query fails.
+    pub async fn finish_task(
+        &self,
+        status: TaskStatus,
+        result: &ResultRecord,
+        phases: &[PhaseRecord],
+    ) -> Result<()> {
+        let started_at = Utc::now();
+        let mut tx = self.pool.begin().await?;
+        update_task_status(&mut *tx, &result.task_id, status).await?;
+        upsert_result(&mut *tx, result).await?;
+        for phase in phases {
+            insert_phase(&mut *tx, phase).await?;
+        }
+        let persist = PhaseRecord {
+            task_id: result.task_id.clone(),
+            name: PhaseName::Persist,
+            started_at,
+            ended_at: Utc::now(),
+            error: None,
+        };
+        insert_phase(&mut *tx, &persist).await?;
+        tx.commit()
+            .await
+            .wrap_err_with(|| format!("failed to finish task {}", result.task_id))?;
+        Ok(())
+    }
+
+    /// All tasks in a job, ordered by target.
+    ///
+    /// # Errors
+    ///
+    /// If the query fails.
+    pub async fn tasks(&self, job_id: &str) -> Result<Vec<TaskRecord>> {
+        let rows = sqlx::query(
+            "SELECT id, job_id, target, param, status FROM task WHERE job_id = ? ORDER BY target, \
+             id",
+        )
+        .bind(job_id)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(task_from_row).collect()
+    }
+}
+
+/// Results
+impl Db {
+    /// Inserts a task result, replacing any prior result for the same task.
+    ///
+    /// # Errors
+    ///
+    /// If the query fails.
+    pub async fn upsert_result(&self, result: &ResultRecord) -> Result<()> {
+        upsert_result(&self.pool, result).await
+    }
+
+    /// All task results in a job.
+    ///
+    /// # Errors
+    ///
+    /// If the query fails.
+    pub async fn results(&self, job_id: &str) -> Result<Vec<ResultRecord>> {
+        let rows = sqlx::query(
+            "SELECT r.task_id, r.stdout, r.stderr, r.exitcode, r.error, r.raw_stdout, r.raw_stderr \
+             FROM result r JOIN task t ON t.id = r.task_id WHERE t.job_id = ? ORDER BY t.target, \
+             t.id",
+        )
+        .bind(job_id)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(result_from_row).collect()
+    }
+}
+
+/// Aggregations
+impl Db {
+    /// Counts tasks in a job by their value of a field, most frequent first.
+    ///
+    /// Every task is counted once, so counts sum to the task count, except for
+    /// [`Field::Error`] where tasks without an error are skipped. Missing
+    /// output is counted as an empty string, and a missing exitcode as `-1`.
+    ///
+    /// # Errors
+    ///
+    /// If the query fails.
+    pub async fn freq(&self, job_id: &str, field: Field) -> Result<Vec<FreqRecord>> {
+        let (value, filter) = match field {
+            Field::Status => ("t.status", ""),
+            Field::Stdout => ("COALESCE(r.stdout, '')", ""),
+            Field::Stderr => ("COALESCE(r.stderr, '')", ""),
+            Field::Exitcode => ("CAST(COALESCE(r.exitcode, -1) AS TEXT)", ""),
+            Field::Error => ("r.error", "AND r.error IS NOT NULL"),
+        };
+        let sql = format!(
+            "SELECT {value} AS value, COUNT(*) AS count FROM task t LEFT JOIN result r ON \
+             r.task_id = t.id WHERE t.job_id = ? {filter} GROUP BY 1 ORDER BY 2 DESC, 1"
+        );
+        let rows = sqlx::query(&sql).bind(job_id).fetch_all(&self.pool).await?;
+        rows.iter().map(freq_from_row).collect()
+    }
+}
+
+/// Phases
+impl Db {
+    /// # Errors
+    ///
+    /// If the query fails.
+    pub async fn insert_phase(&self, phase: &PhaseRecord) -> Result<()> {
+        insert_phase(&self.pool, phase).await
+    }
+
+    /// All task phases in a job, in the order they started.
+    ///
+    /// # Errors
+    ///
+    /// If the query fails.
+    pub async fn phases(&self, job_id: &str) -> Result<Vec<PhaseRecord>> {
+        let rows = sqlx::query(
+            "SELECT p.task_id, p.name, p.started_at, p.ended_at, p.error FROM phase p JOIN task t \
+             ON t.id = p.task_id WHERE t.job_id = ? ORDER BY p.started_at",
+        )
+        .bind(job_id)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(phase_from_row).collect()
+    }
+}
+
+/// Maintenance
+impl Db {
+    /// Deletes jobs along with their tasks, results and phases. The latest
+    /// job is forgotten if it is deleted.
+    ///
+    /// # Errors
+    ///
+    /// If the query fails.
+    pub async fn delete_jobs(&self, job_ids: &[String]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for job_id in job_ids {
+            sqlx::query("DELETE FROM job WHERE id = ?")
+                .bind(job_id)
+                .execute(&mut *tx)
+                .await
+                .wrap_err_with(|| format!("failed to delete job {job_id}"))?;
+            sqlx::query("DELETE FROM meta WHERE key = ? AND value = ?")
+                .bind(LATEST_JOB_KEY)
+                .bind(job_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Size of the database in bytes, including pages freed by deletes until
+    /// it is vacuumed.
+    ///
+    /// # Errors
+    ///
+    /// If the query fails.
+    pub async fn size(&self) -> Result<u64> {
+        let size: i64 = sqlx::query_scalar(
+            "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(size.try_into()?)
+    }
+
+    /// Rebuilds the database to return freed pages to the filesystem, then
+    /// truncates the write-ahead log that the rebuild went through.
+    ///
+    /// # Errors
+    ///
+    /// If the query fails, such as when another process is using the
+    /// database.
+    pub async fn vacuum(&self) -> Result<()> {
+        sqlx::query("VACUUM")
+            .execute(&self.pool)
+            .await
+            .wrap_err("failed to vacuum database")?;
+        sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
+
+async fn update_task_status(
+    executor: impl SqliteExecutor<'_>,
+    task_id: &str,
+    status: TaskStatus,
+) -> Result<()> {
+    sqlx::query("UPDATE task SET status = ? WHERE id = ?")
+        .bind(status.to_string())
+        .bind(task_id)
+        .execute(executor)
+        .await
+        .wrap_err_with(|| format!("failed to update status of task {task_id}"))?;
+    Ok(())
+}
+
+async fn upsert_result(executor: impl SqliteExecutor<'_>, result: &ResultRecord) -> Result<()> {
+    sqlx::query(
+        "INSERT OR REPLACE INTO result (task_id, stdout, stderr, exitcode, error, raw_stdout, \
+         raw_stderr) VALUES (?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&result.task_id)
+    .bind(&result.stdout)
+    .bind(&result.stderr)
+    .bind(result.exitcode)
+    .bind(&result.error)
+    .bind(&result.raw_stdout)
+    .bind(&result.raw_stderr)
+    .execute(executor)
+    .await
+    .wrap_err_with(|| format!("failed to upsert result for task {}", result.task_id))?;
+    Ok(())
+}
+
+async fn insert_phase(executor: impl SqliteExecutor<'_>, phase: &PhaseRecord) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO phase (task_id, name, started_at, ended_at, error) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(&phase.task_id)
+    .bind(phase.name.to_string())
+    .bind(phase.started_at)
+    .bind(phase.ended_at)
+    .bind(&phase.error)
+    .execute(executor)
+    .await
+    .wrap_err_with(|| format!("failed to insert phase for task {}", phase.task_id))?;
+    Ok(())
+}
+
+fn job_from_row(row: &SqliteRow) -> Result<JobRecord> {
+    let Json(cmdline) = row.try_get("cmdline")?;
+    let Json(plan) = row.try_get("plan")?;
+    Ok(JobRecord {
+        id: row.try_get("id")?,
+        started_at: row.try_get("started_at")?,
+        cmdline,
+        plan,
+    })
+}
+
+fn task_from_row(row: &SqliteRow) -> Result<TaskRecord> {
+    let target: String = row.try_get("target")?;
+    let status: String = row.try_get("status")?;
+    Ok(TaskRecord {
+        id: row.try_get("id")?,
+        job_id: row.try_get("job_id")?,
+        target: Target::from_str(&target)?,
+        param: row.try_get("param")?,
+        status: TaskStatus::from_str(&status)
+            .wrap_err_with(|| format!("unknown task status: {status}"))?,
+    })
+}
+
+fn result_from_row(row: &SqliteRow) -> Result<ResultRecord> {
+    Ok(ResultRecord {
+        task_id: row.try_get("task_id")?,
+        stdout: row.try_get("stdout")?,
+        stderr: row.try_get("stderr")?,
+        exitcode: row.try_get("exitcode")?,
+        error: row.try_get("error")?,
+        raw_stdout: row.try_get("raw_stdout")?,
+        raw_stderr: row.try_get("raw_stderr")?,
+    })
+}
+
+fn freq_from_row(row: &SqliteRow) -> Result<FreqRecord> {
+    let count: i64 = row.try_get("count")?;
+    Ok(FreqRecord {
+        value: row.try_get("value")?,
+        count: count.try_into()?,
+    })
+}
+
+fn phase_from_row(row: &SqliteRow) -> Result<PhaseRecord> {
+    let name: String = row.try_get("name")?;
+    Ok(PhaseRecord {
+        task_id: row.try_get("task_id")?,
+        name: name
+            .parse()
+            .wrap_err_with(|| format!("unknown phase name: {name}"))?,
+        started_at: row.try_get("started_at")?,
+        ended_at: row.try_get("ended_at")?,
+        error: row.try_get("error")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn job(id: &str) -> JobRecord {
+        JobRecord {
+            id: id.to_owned(),
+            started_at: Utc::now(),
+            cmdline: vec!["astu".into(), "ping".into()],
+            plan: serde_json::json!({ "action": "ping" }),
+        }
+    }
+
+    fn task(id: &str, job_id: &str, target: &str) -> eyre::Result<TaskRecord> {
+        Ok(TaskRecord {
+            id: id.to_owned(),
+            job_id: job_id.to_owned(),
+            target: Target::from_str(target)?,
+            param: None,
+            status: TaskStatus::Pending,
+        })
+    }
+
+    #[tokio::test]
+    async fn job_roundtrip_works() -> eyre::Result<()> {
+        let db = Db::open_in_memory().await?;
+        let job = job("j1");
+        db.insert_job(&job).await?;
+        assert_eq!(db.job("j1").await?, Some(job));
+        assert_eq!(db.job("j2").await?, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn job_summaries_work() -> eyre::Result<()> {
+        let db = Db::open_in_memory().await?;
+        db.insert_job(&job("j1")).await?;
+        db.insert_job(&job("j2")).await?;
+        db.insert_tasks(&[
+            task("t1", "j1", "10.0.0.1")?,
+            task("t2", "j1", "10.0.0.2")?,
+            task("t3", "j1", "10.0.0.3")?,
+        ])
+        .await?;
+        db.set_task_status("t1", TaskStatus::Failed).await?;
+        db.set_task_status("t2", TaskStatus::Complete).await?;
+
+        let summaries = db.job_summaries().await?;
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(summaries[0].job.id, "j1");
+        assert_eq!(summaries[0].task_count(), 3);
+        assert_eq!(summaries[0].count(TaskStatus::Failed), 1);
+        assert_eq!(summaries[0].count(TaskStatus::Canceled), 0);
+        assert_eq!(summaries[1].task_count(), 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn latest_job_works() -> eyre::Result<()> {
+        let db = Db::open_in_memory().await?;
+        assert_eq!(db.latest_job().await?, None);
+        assert!(db.job_or_latest(None).await.is_err());
+
+        db.insert_job(&job("j1")).await?;
+        db.insert_job(&job("j2")).await?;
+        db.set_latest_job("j1").await?;
+        db.set_latest_job("j2").await?;
+
+        assert_eq!(db.latest_job().await?.as_deref(), Some("j2"));
+        assert_eq!(db.job_or_latest(None).await?, "j2");
+        assert_eq!(db.job_or_latest(Some("j1")).await?, "j1");
+        assert!(db.job_or_latest(Some("j3")).await.is_err());
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(TaskStatus::Running)]
+    #[case(TaskStatus::Complete)]
+    #[case(TaskStatus::Failed)]
+    #[case(TaskStatus::Canceled)]
+    #[tokio::test]
+    async fn task_status_works(#[case] status: TaskStatus) -> eyre::Result<()> {
+        let db = Db::open_in_memory().await?;
+        db.insert_job(&job("j1")).await?;
+        db.insert_tasks(&[task("t1", "j1", "127.0.0.1")?]).await?;
+        db.set_task_status("t1", status).await?;
+
+        let tasks = db.tasks("j1").await?;
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].status, status);
+        assert_eq!(tasks[0].target.to_string(), "ip://127.0.0.1");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn set_job_task_status_works() -> eyre::Result<()> {
+        let db = Db::open_in_memory().await?;
+        db.insert_job(&job("j1")).await?;
+        db.insert_job(&job("j2")).await?;
+        db.insert_tasks(&[
+            task("t1", "j1", "10.0.0.1")?,
+            task("t2", "j1", "10.0.0.2")?,
+            task("t3", "j1", "10.0.0.3")?,
+            task("t4", "j2", "10.0.0.1")?,
+        ])
+        .await?;
+        db.set_task_status("t1", TaskStatus::Complete).await?;
+        db.set_task_status("t2", TaskStatus::Running).await?;
+
+        let from = [TaskStatus::Pending, TaskStatus::Running];
+        let count = db
+            .set_job_task_status("j1", &from, TaskStatus::Canceled)
+            .await?;
+        assert_eq!(count, 2);
+        let statuses: Vec<_> = db.tasks("j1").await?.iter().map(|x| x.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                TaskStatus::Complete,
+                TaskStatus::Canceled,
+                TaskStatus::Canceled
+            ]
+        );
+        assert_eq!(db.tasks("j2").await?[0].status, TaskStatus::Pending);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn task_param_works() -> eyre::Result<()> {
+        let db = Db::open_in_memory().await?;
+        db.insert_job(&job("j1")).await?;
+        let with_param = TaskRecord {
+            param: Some("https://example.com".into()),
+            ..task("t1", "j1", "local:")?
+        };
+        db.insert_tasks(&[with_param.clone(), task("t2", "j1", "local:")?])
+            .await?;
+
+        let tasks = db.tasks("j1").await?;
+        assert_eq!(tasks[0], with_param);
+        assert_eq!(tasks[1].param, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn results_and_phases_work() -> eyre::Result<()> {
+        let db = Db::open_in_memory().await?;
+        db.insert_job(&job("j1")).await?;
+        db.insert_job(&job("j2")).await?;
+        db.insert_tasks(&[
+            task("t1", "j1", "127.0.0.1")?,
+            task("t2", "j1", "127.0.0.2")?,
+            task("t3", "j2", "127.0.0.3")?,
+        ])
+        .await?;
+
+        let first = ResultRecord {
+            task_id: "t1".into(),
+            stdout: Some("foo".into()),
+            exitcode: Some(0),
+            ..Default::default()
+        };
+        let replaced = ResultRecord {
+            task_id: "t1".into(),
+            stdout: Some("bar".into()),
+            exitcode: Some(1),
+            ..Default::default()
+        };
+        let other = ResultRecord {
+            task_id: "t3".into(),
+            error: Some("boom".into()),
+            ..Default::default()
+        };
+        db.upsert_result(&first).await?;
+        db.upsert_result(&replaced).await?;
+        db.upsert_result(&other).await?;
+        assert_eq!(db.results("j1").await?, vec![replaced]);
+
+        let now = Utc::now();
+        let phase = PhaseRecord {
+            task_id: "t2".into(),
+            name: PhaseName::Connect,
+            started_at: now,
+            ended_at: now,
+            error: Some("TCP connect failed".into()),
+        };
+        db.insert_phase(&phase).await?;
+        assert_eq!(db.phases("j1").await?, vec![phase]);
+        assert!(db.phases("j2").await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn raw_output_round_trips() -> eyre::Result<()> {
+        let db = Db::open_in_memory().await?;
+        db.insert_job(&job("j1")).await?;
+        db.insert_tasks(&[task("t1", "j1", "127.0.0.1")?]).await?;
+
+        let result = ResultRecord {
+            task_id: "t1".into(),
+            stdout: Some("hello from {host}".into()),
+            stderr: Some("{host}: warning".into()),
+            exitcode: Some(0),
+            raw_stdout: Some("hello from 127.0.0.1".into()),
+            raw_stderr: Some("127.0.0.1: warning".into()),
+            ..Default::default()
+        };
+        db.upsert_result(&result).await?;
+        assert_eq!(db.results("j1").await?, vec![result]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn freq_works() -> eyre::Result<()> {
+        let db = Db::open_in_memory().await?;
+        db.insert_job(&job("j1")).await?;
+        db.insert_tasks(&[
+            task("t1", "j1", "127.0.0.1")?,
+            task("t2", "j1", "127.0.0.2")?,
+            task("t3", "j1", "127.0.0.3")?,
+        ])
+        .await?;
+        for (task_id, stdout, exitcode, error) in [
+            ("t1", Some("foo"), Some(0), None),
+            ("t2", Some("foo"), Some(0), None),
+            ("t3", None, None, Some("boom")),
+        ] {
+            let result = ResultRecord {
+                task_id: task_id.into(),
+                stdout: stdout.map(Into::into),
+                exitcode,
+                error: error.map(Into::into),
+                ..Default::default()
+            };
+            let status = if error.is_some() {
+                TaskStatus::Failed
+            } else {
+                TaskStatus::Complete
+            };
+            db.finish_task(status, &result, &[]).await?;
+        }
+
+        let freq = |value: &str, count| FreqRecord {
+            value: value.into(),
+            count,
+        };
+        assert_eq!(db.task_count("j1").await?, 3);
+        assert_eq!(
+            db.freq("j1", Field::Status).await?,
+            vec![freq("complete", 2), freq("failed", 1)]
+        );
+        assert_eq!(
+            db.freq("j1", Field::Stdout).await?,
+            vec![freq("foo", 2), freq("", 1)]
+        );
+        assert_eq!(
+            db.freq("j1", Field::Exitcode).await?,
+            vec![freq("0", 2), freq("-1", 1)]
+        );
+        assert_eq!(db.freq("j1", Field::Error).await?, vec![freq("boom", 1)]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn finish_task_records_persist_phase() -> eyre::Result<()> {
+        let db = Db::open_in_memory().await?;
+        db.insert_job(&job("j1")).await?;
+        db.insert_tasks(&[task("t1", "j1", "127.0.0.1")?]).await?;
+
+        let now = Utc::now();
+        let connect = PhaseRecord {
+            task_id: "t1".into(),
+            name: PhaseName::Connect,
+            started_at: now,
+            ended_at: now,
+            error: None,
+        };
+        let result = ResultRecord {
+            task_id: "t1".into(),
+            exitcode: Some(0),
+            ..Default::default()
+        };
+        db.finish_task(
+            TaskStatus::Complete,
+            &result,
+            std::slice::from_ref(&connect),
+        )
+        .await?;
+
+        let phases = db.phases("j1").await?;
+        let names: Vec<_> = phases.iter().map(|x| x.name).collect();
+        assert_eq!(names, vec![PhaseName::Connect, PhaseName::Persist]);
+        assert_eq!(phases[0], connect);
+        assert!(phases[1].started_at <= phases[1].ended_at);
+        assert_eq!(phases[1].error, None);
+        assert_eq!(db.results("j1").await?, vec![result]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn delete_jobs_works() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let db = Db::open(dir.path()).await?;
+        for job_id in ["j1", "j2"] {
+            db.insert_job(&job(job_id)).await?;
+            let tasks: Vec<_> = (0..100)
+                .map(|i| task(&format!("{job_id}-t{i}"), job_id, "10.0.0.1"))
+                .collect::<eyre::Result<_>>()?;
+            db.insert_tasks(&tasks).await?;
+            for task in &tasks {
+                let result = ResultRecord {
+                    task_id: task.id.clone(),
+                    stdout: Some("x".repeat(1000)),
+                    ..Default::default()
+                };
+                db.finish_task(TaskStatus::Complete, &result, &[]).await?;
+            }
+        }
+        db.set_latest_job("j1").await?;
+
+        let before = db.size().await?;
+        db.delete_jobs(&["j1".into()]).await?;
+        db.vacuum().await?;
+        assert!(db.size().await? < before);
+        assert_eq!(db.job("j1").await?, None);
+        assert_eq!(db.task_count("j1").await?, 0);
+        assert!(db.results("j1").await?.is_empty());
+        assert_eq!(db.latest_job().await?, None);
+        assert_eq!(db.task_count("j2").await?, 100);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn open_migrates_idempotently() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let data_dir = dir.path().join("nested");
+        {
+            let db = Db::open(&data_dir).await?;
+            db.insert_job(&job("j1")).await?;
+        }
+        let db = Db::open(&data_dir).await?;
+        assert!(db.job("j1").await?.is_some());
+        Ok(())
+    }
+}
@@ -1 +1,15 @@
-//! Database layer is intentionally reset and rebuilt from scratch.
+//! Persistence of jobs, tasks and their results.
+
+mod db;
+mod model;
+
+pub use crate::db::Db;
+pub use crate::model::Field;
+pub use crate::model::FreqRecord;
+pub use crate::model::JobRecord;
+pub use crate::model::JobSummary;
+pub use crate::model::PhaseName;
+pub use crate::model::PhaseRecord;
+pub use crate::model::ResultRecord;
+pub use crate::model::TaskRecord;
+pub use crate::model::TaskStatus;
@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+
+use astu_types::Target;
+use chrono::DateTime;
+use chrono::Utc;
+use serde::Deserialize;
+use serde::Serialize;
+use strum::Display;
+use strum::EnumString;
+
+/// A single invocation of an action subcommand.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JobRecord {
+    pub id: String,
+    pub started_at: DateTime<Utc>,
+    /// Full cmdline of the process that started the job.
+    pub cmdline: Vec<String>,
+    /// Opaque description of the job plan.
+    pub plan: serde_json::Value,
+}
+
+/// A job with the number of its tasks in each status.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JobSummary {
+    #[serde(flatten)]
+    pub job: JobRecord,
+    /// Number of tasks by status, without statuses that no task has.
+    pub task_counts: BTreeMap<TaskStatus, u64>,
+}
+
+impl JobSummary {
+    /// Number of tasks in the job.
+    #[must_use]
+    pub fn task_count(&self) -> u64 {
+        self.task_counts.values().sum()
+    }
+
+    /// Number of tasks in the job with a status.
+    #[must_use]
+    pub fn count(&self, status: TaskStatus) -> u64 {
+        self.task_counts.get(&status).copied().unwrap_or_default()
+    }
+}
+
+/// The unit of work performed on a single target within a job.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskRecord {
+    pub id: String,
+    pub job_id: String,
+    pub target: Target,
+    /// Parameter substituted for `{param}`, if the job fans out over
+    /// parameters.
+    pub param: Option<String>,
+    pub status: TaskStatus,
+}
+
+/// Lifecycle state of a task.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Display,
+    EnumString,
+    Serialize,
+    Deserialize,
+)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum TaskStatus {
+    /// Not yet started.
+    Pending,
+    /// Currently running.
+    Running,
+    /// Finished without error.
+    Complete,
+    /// Finished with an error.
+    Failed,
+    /// Canceled before it could start.
+    Canceled,
+}
+
+/// Captured output of a task.
+///
+/// Output may be deduplicated, in which case the raw output is kept as well.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResultRecord {
+    pub task_id: String,
+    pub stdout: Option<String>,
+    pub stderr: Option<String>,
+    pub exitcode: Option<i32>,
+    pub error: Option<String>,
+    /// Stdout as captured, if deduplication changed it.
+    pub raw_stdout: Option<String>,
+    /// Stderr as captured, if deduplication changed it.
+    pub raw_stderr: Option<String>,
+}
+
+/// Timing of a single phase of a task.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PhaseRecord {
+    pub task_id: String,
+    pub name: PhaseName,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    pub error: Option<String>,
+}
+
+/// Named phases that a task may go through.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Display,
+    EnumString,
+    Serialize,
+    Deserialize,
+)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum PhaseName {
+    /// Looking up the address of the target.
+    Resolve,
+    Connect,
+    Auth,
+    Ping,
+    Exec,
+    Wait,
+    /// Writing the result of the task to the database.
+    Persist,
+}
+
+/// Task result fields that may be aggregated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum Field {
+    Status,
+    Stdout,
+    Stderr,
+    Exitcode,
+    Error,
+}
+
+/// Count of tasks in a job sharing the same value for a [`Field`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FreqRecord {
+    pub value: String,
+    pub count: u64,
+}
@@ -9,7 +9,13 @@ use futures::stream::BoxStream;
 
 pub use self::provider::ChainResolver;
 pub use self::provider::CidrResolver;
+pub use self::provider::DnsConfig;
 pub use self::provider::DnsResolver;
+pub use self::provider::FileResolver;
+pub use self::provider::HostsFileResolver;
+pub use self::provider::KnownHostsResolver;
+pub use self::provider::LookupStrategy;
+pub use self::provider::SshConfigResolver;
 pub use self::provider::forward_chain;
 pub use self::provider::reverse_chain;
 
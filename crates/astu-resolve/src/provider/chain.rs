@@ -11,11 +11,13 @@ use crate::Resolve;
 
 /// Composite resolver that flattens the streams of a set of resolvers into one.
 ///
-/// If none of the constituent resolvers can resolve a given target, that target
+/// Fallback resolvers are only asked for targets that none of the other
+/// resolvers resolved. If no resolver can resolve a given target, that target
 /// itself is returned.
 #[derive(Clone, Default)]
 pub struct ChainResolver {
     resolvers: Vec<Arc<dyn Resolve + Send + Sync>>,
+    fallbacks: Vec<Arc<dyn Resolve + Send + Sync>>,
 }
 
 impl fmt::Debug for ChainResolver {
@@ -28,7 +30,17 @@ impl Resolve for ChainResolver {
     fn resolve_fallible(&self, target: Target) -> BoxStream<'_, Result<Target>> {
         stream! {
             let mut bounce_original = true;
+            let mut resolved = false;
             for resolver in &self.resolvers {
+                let mut stream = resolver.resolve_fallible(target.clone());
+                while let Some(result) = stream.next().await {
+                    bounce_original = false;
+                    resolved |= result.is_ok();
+                    yield result;
+                }
+            }
+            let fallbacks = if resolved { &[][..] } else { &self.fallbacks[..] };
+            for resolver in fallbacks {
                 let mut stream = resolver.resolve_fallible(target.clone());
                 while let Some(result) = stream.next().await {
                     bounce_original = false;
@@ -49,6 +61,14 @@ impl ChainResolver {
         self.resolvers.push(Arc::new(resolver));
         self
     }
+
+    /// Adds a resolver that is only asked for targets that no other resolver
+    /// resolved.
+    #[must_use]
+    pub fn with_fallback(mut self, resolver: impl Resolve + Send + Sync + 'static) -> Self {
+        self.fallbacks.push(Arc::new(resolver));
+        self
+    }
 }
 
 #[cfg(test)]
@@ -59,7 +79,10 @@ mod tests {
 
     use super::*;
     use crate::CidrResolver;
+    use crate::DnsConfig;
     use crate::DnsResolver;
+    use crate::KnownHostsResolver;
+    use crate::LookupStrategy;
     use crate::ResolveExt;
 
     #[rstest]
@@ -69,12 +92,37 @@ mod tests {
     #[tokio::test]
     async fn resolve_works(#[case] query: &str, #[case] num: usize) -> eyre::Result<()> {
         let target = Target::from_str(query)?;
+        let dns = DnsConfig::builder().strategy(LookupStrategy::Ipv4).build();
         let resolver = ChainResolver::default()
             .with(CidrResolver::default())
-            .with(DnsResolver::try_new()?);
+            .with(DnsResolver::from_config(&dns)?);
         let targets = resolver.resolve_set(target).await;
         assert_eq!(targets.len(), num);
 
         Ok(())
     }
+
+    #[rstest]
+    #[case("web1.example.com", &["ip://10.0.0.1"])]
+    #[case("web2.example.com", &["ip://10.0.0.2"])]
+    #[case("web3.example.com", &["dns://web3.example.com"])]
+    #[tokio::test]
+    async fn resolve_falls_back(#[case] query: &str, #[case] should: &[&str]) -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let primary = dir.path().join("primary");
+        std::fs::write(&primary, "web1.example.com,10.0.0.1 ssh-ed25519 AAAAkey1\n")?;
+        let fallback = dir.path().join("fallback");
+        std::fs::write(
+            &fallback,
+            "web1.example.com,10.0.0.9 ssh-ed25519 AAAAkey1\nweb2.example.com,10.0.0.2 ssh-ed25519 AAAAkey2\n",
+        )?;
+        let resolver = ChainResolver::default()
+            .with(KnownHostsResolver::from_path(primary)?)
+            .with_fallback(KnownHostsResolver::from_path(fallback)?);
+
+        let targets = resolver.resolve_set(Target::from_str(query)?).await;
+        let targets: Vec<_> = targets.iter().map(ToString::to_string).collect();
+        assert_eq!(targets, should);
+        Ok(())
+    }
 }
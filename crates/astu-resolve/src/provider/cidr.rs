@@ -27,7 +27,7 @@ impl CidrResolver {
         let ips = cidr.hosts().map(move |ip| {
             let port = target.port();
             let user = target.user();
-            Target::new_ip(&ip, port, user)
+            Target::new_ip(&ip, port, user.as_deref())
         });
         futures::stream::iter(ips).boxed()
     }
@@ -1,56 +1,241 @@
+use std::collections::BTreeMap;
 use std::net::IpAddr;
+use std::net::SocketAddr;
+use std::path::Path;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 
 use astu_types::Host;
 use astu_types::Target;
+use astu_types::TargetKind;
 use async_stream::try_stream;
+use bon::Builder;
 use eyre::Result;
-use eyre::eyre;
+use eyre::WrapErr;
 use futures::StreamExt;
 use futures::stream::BoxStream;
+use hickory_proto::serialize::txt::Parser;
 use hickory_resolver::Name;
 use hickory_resolver::TokioResolver;
-use hickory_resolver::proto::rr::rdata::PTR;
+use hickory_resolver::config::LookupIpStrategy;
+use hickory_resolver::config::NameServerConfig;
+use hickory_resolver::config::NameServerConfigGroup;
+use hickory_resolver::config::ResolverConfig;
+use hickory_resolver::config::ResolverOpts;
+use hickory_resolver::name_server::TokioConnectionProvider;
+use hickory_resolver::proto::rr::RData;
+use hickory_resolver::proto::rr::rdata::SRV;
+use hickory_resolver::proto::xfer::Protocol;
 
 use crate::Resolve;
+use crate::provider::glob::is_pattern;
+use crate::provider::glob::matches_glob;
+
+/// Maximum number of CNAME records followed when resolving a name in a zone.
+const MAX_CNAME_DEPTH: usize = 8;
 
 /// Resolves DNS queries - both forward and reverse - into targets.
+///
+/// Forward resolution applies to DNS and DNS SRV targets, and reverse
+/// resolution applies to IP targets. Other target kinds are left to their own
+/// resolvers.
+///
+/// SRV targets resolve to a DNS target with a port for each SRV record,
+/// except those aimed at `.`, which mark the service as unavailable. Records
+/// of priorities other than the most preferred (lowest) one are backups, and
+/// may be left out with [`DnsResolver::with_srv_preferred`]. Weights only
+/// spread load within a priority, so they do not affect which records are
+/// resolved.
+///
+/// Queries are sent to nameservers, or answered from the records of a zone
+/// file. Only a zone can be listed, so DNS targets with `*` or `?` wildcards,
+/// like `*.lab.example.com`, only resolve from a zone, to the addresses of
+/// every name that they match.
 #[derive(Debug, Clone)]
 pub struct DnsResolver {
-    dns: TokioResolver,
+    backend: Backend,
     forward: bool,
     reverse: bool,
+    srv_preferred: bool,
+}
+
+/// Settings for querying nameservers. Settings that are not set are taken
+/// from the system config, such as `/etc/resolv.conf`.
+#[derive(Debug, Clone, Default, Builder)]
+pub struct DnsConfig {
+    /// Nameservers to query instead of the system ones, over UDP and TCP.
+    #[builder(default)]
+    nameservers: Vec<SocketAddr>,
+
+    /// Address families to look up.
+    #[builder(default)]
+    strategy: LookupStrategy,
+
+    /// Timeout of each query.
+    timeout: Option<Duration>,
+
+    /// Number of times that each query is attempted.
+    attempts: Option<usize>,
+
+    /// Domains to search for names that are not fully qualified, instead of
+    /// the system ones.
+    #[builder(default)]
+    search: Vec<String>,
+
+    /// Maximum number of responses cached.
+    cache_size: Option<usize>,
+
+    /// Whether SRV targets only resolve to the records of the most preferred
+    /// priority.
+    #[builder(default)]
+    srv_preferred: bool,
+}
+
+/// Address families that forward resolution looks up.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LookupStrategy {
+    /// IPv4 addresses only
+    Ipv4,
+
+    /// IPv6 addresses only
+    Ipv6,
+
+    /// Both IPv4 and IPv6 addresses
+    #[default]
+    Both,
+}
+
+impl From<LookupStrategy> for LookupIpStrategy {
+    fn from(value: LookupStrategy) -> Self {
+        match value {
+            LookupStrategy::Ipv4 => Self::Ipv4Only,
+            LookupStrategy::Ipv6 => Self::Ipv6Only,
+            LookupStrategy::Both => Self::Ipv4AndIpv6,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Backend {
+    Nameservers(Box<TokioResolver>),
+    Zone(Arc<Zone>),
 }
 
 impl Resolve for DnsResolver {
     fn resolve_fallible(&self, target: Target) -> BoxStream<'_, Result<Target>> {
-        let fwd = self.forward;
-        let rev = self.reverse;
+        let fwd = self.forward && target.kind() == TargetKind::Dns;
+        let srv = self.forward && target.kind() == TargetKind::DnsSrv;
+        let rev = self.reverse && target.kind() == TargetKind::Ip;
         match target.host() {
             Some(Host::Domain(name)) if fwd => self.resolve_domain(name, target),
+            Some(Host::Domain(name)) if srv => self.resolve_srv(name, target),
             Some(Host::Ip(ip)) if rev => self.resolve_ip(ip, target),
             _unsupported => futures::stream::empty().boxed(),
         }
     }
 }
 
+/// Constructors
 impl DnsResolver {
-    /// Creates a DNS resolver using the system DNS config. Forward resolution
-    /// is enabled by default, while reverse resolution is disabled.
+    /// Creates a DNS resolver using the system DNS config, looking up both
+    /// IPv4 and IPv6 addresses. Forward resolution is enabled by default, while
+    /// reverse resolution is disabled.
     ///
     /// # Errors
     ///
     /// - If the system resolver config fails to build.
     pub fn try_new() -> Result<Self> {
-        // TODO: Use `Ipv4AndIpv6` strategy instead of the default `Ipv4thenIpv6`
-        let dns = TokioResolver::builder_tokio()?.build();
-        Ok(Self {
-            dns,
+        Self::from_config(&DnsConfig::default())
+    }
+
+    /// Creates a DNS resolver using the system DNS config, overridden by the
+    /// settings that are set. Forward resolution is enabled by default, while
+    /// reverse resolution is disabled.
+    ///
+    /// # Errors
+    ///
+    /// - If the system resolver config fails to build, unless nameservers are
+    ///   set.
+    /// - If a search domain is invalid.
+    pub fn from_config(config: &DnsConfig) -> Result<Self> {
+        let (system, mut opts) = match hickory_resolver::system_conf::read_system_conf() {
+            Ok(system) => system,
+            // The system config is only needed for its nameservers.
+            Err(_) if !config.nameservers.is_empty() => {
+                (ResolverConfig::new(), ResolverOpts::default())
+            }
+            Err(error) => return Err(error).wrap_err("failed to read system DNS config"),
+        };
+
+        let name_servers = if config.nameservers.is_empty() {
+            system.name_servers().to_vec()
+        } else {
+            config
+                .nameservers
+                .iter()
+                .flat_map(|&addr| {
+                    [Protocol::Udp, Protocol::Tcp].map(|x| NameServerConfig::new(addr, x))
+                })
+                .collect()
+        };
+        let search = if config.search.is_empty() {
+            system.search().to_vec()
+        } else {
+            config
+                .search
+                .iter()
+                .map(|x| Name::from_str(x).wrap_err_with(|| format!("invalid search domain: {x}")))
+                .collect::<Result<_>>()?
+        };
+        let resolver_config = ResolverConfig::from_parts(
+            system.domain().cloned(),
+            search,
+            NameServerConfigGroup::from(name_servers),
+        );
+
+        opts.ip_strategy = config.strategy.into();
+        if let Some(timeout) = config.timeout {
+            opts.timeout = timeout;
+        }
+        if let Some(attempts) = config.attempts {
+            opts.attempts = attempts;
+        }
+        if let Some(cache_size) = config.cache_size {
+            opts.cache_size = cache_size;
+        }
+
+        let dns =
+            TokioResolver::builder_with_config(resolver_config, TokioConnectionProvider::default())
+                .with_options(opts)
+                .build();
+        let resolver = Self::new(Backend::Nameservers(Box::new(dns)));
+        Ok(resolver.with_srv_preferred(config.srv_preferred))
+    }
+
+    /// Creates a DNS resolver that answers from the records of a zone file in
+    /// RFC 1035 format, without querying nameservers. Forward resolution is
+    /// enabled by default, while reverse resolution is disabled.
+    ///
+    /// # Errors
+    ///
+    /// - If the zone file cannot be read or parsed.
+    pub fn from_zone_file(path: impl AsRef<Path>) -> Result<Self> {
+        let zone = Zone::from_path(path.as_ref())?;
+        Ok(Self::new(Backend::Zone(Arc::new(zone))))
+    }
+
+    const fn new(backend: Backend) -> Self {
+        Self {
+            backend,
             forward: true,
             reverse: false,
-        })
+            srv_preferred: false,
+        }
     }
+}
 
+impl DnsResolver {
     /// Set forward lookup.
     #[must_use]
     pub const fn with_forward(mut self, enable: bool) -> Self {
@@ -65,14 +250,47 @@ impl DnsResolver {
         self
     }
 
+    /// Set whether SRV targets only resolve to the records of the most
+    /// preferred priority, leaving out backups.
+    #[must_use]
+    pub const fn with_srv_preferred(mut self, enable: bool) -> Self {
+        self.srv_preferred = enable;
+        self
+    }
+
     /// Forward DNS resolution
     fn resolve_domain(&self, name: String, target: Target) -> BoxStream<'_, Result<Target>> {
         try_stream! {
-            let ips = self.dns.lookup_ip(name).await?;
+            let ips: Vec<IpAddr> = match &self.backend {
+                Backend::Nameservers(_) if is_pattern(&name) => Vec::new(),
+                Backend::Nameservers(dns) => dns.lookup_ip(name).await?.iter().collect(),
+                Backend::Zone(zone) => zone.lookup_ip(&name),
+            };
             for ip in ips {
                 let port = target.port();
                 let user = target.user();
-                yield Target::new_ip(&ip, port, user)?
+                yield Target::new_ip(&ip, port, user.as_deref())?
+            }
+        }
+        .boxed()
+    }
+
+    /// Service resolution
+    fn resolve_srv(&self, name: String, target: Target) -> BoxStream<'_, Result<Target>> {
+        try_stream! {
+            let records: Vec<SRV> = match &self.backend {
+                Backend::Nameservers(dns) => dns.srv_lookup(name).await?.into_iter().collect(),
+                Backend::Zone(zone) => zone.lookup_srv(&name),
+            };
+            // A target of `.` means that the service is not available.
+            let records: Vec<_> = records.into_iter().filter(|x| !x.target().is_root()).collect();
+            let preferred = records.iter().map(SRV::priority).min();
+            let records = records
+                .iter()
+                .filter(|x| !self.srv_preferred || Some(x.priority()) == preferred);
+            for srv in records {
+                let user = target.user();
+                yield Target::new_dns(&to_domain(srv.target()), Some(srv.port()), user.as_deref())?
             }
         }
         .boxed()
@@ -81,25 +299,112 @@ impl DnsResolver {
     /// Reverse DNS resolution
     fn resolve_ip(&self, ip: IpAddr, target: Target) -> BoxStream<'_, Result<Target>> {
         try_stream! {
-            let names = self.dns.reverse_lookup(ip).await?;
-            for ptr in names {
-                let domain = remove_trailing_dot(&ptr)?;
+            let names: Vec<String> = match &self.backend {
+                Backend::Nameservers(dns) => dns
+                    .reverse_lookup(ip)
+                    .await?
+                    .iter()
+                    .map(|ptr| to_domain(&ptr.0))
+                    .collect(),
+                Backend::Zone(zone) => zone.lookup_ptr(ip),
+            };
+            for domain in names {
                 let port = target.port();
                 let user = target.user();
-                yield Target::new_dns(&domain.to_string(), port, user)?
+                yield Target::new_dns(&domain, port, user.as_deref())?
             }
         }
         .boxed()
     }
 }
 
-fn remove_trailing_dot(ptr: &PTR) -> Result<Name> {
-    let name = ptr.0.to_string();
-    let name = name
-        .strip_suffix('.')
-        .ok_or_else(|| eyre!("unable to strip trailing dot"))?;
-    let name = Name::from_str(name)?;
-    Ok(name)
+/// Records of a zone file.
+#[derive(Debug, Default)]
+struct Zone {
+    /// Record data by lowercase owner name.
+    records: BTreeMap<String, Vec<RData>>,
+}
+
+impl Zone {
+    fn from_path(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read zone file {}", path.display()))?;
+        let (_origin, record_sets) = Parser::new(contents, Some(path.to_owned()), None)
+            .parse()
+            .wrap_err_with(|| format!("failed to parse zone file {}", path.display()))?;
+
+        let mut records: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for record in record_sets
+            .values()
+            .flat_map(hickory_resolver::proto::rr::RecordSet::records_without_rrsigs)
+        {
+            let name = to_domain(record.name()).to_ascii_lowercase();
+            records.entry(name).or_default().push(record.data().clone());
+        }
+        Ok(Self { records })
+    }
+
+    /// Addresses of a name, or of every name that a pattern matches.
+    fn lookup_ip(&self, name: &str) -> Vec<IpAddr> {
+        let name = name.to_ascii_lowercase();
+        let mut ips = Vec::new();
+        if is_pattern(&name) {
+            for owner in self.records.keys().filter(|x| matches_glob(&name, x)) {
+                self.collect_ips(owner, 0, &mut ips);
+            }
+        } else {
+            self.collect_ips(&name, 0, &mut ips);
+        }
+        ips
+    }
+
+    fn collect_ips(&self, name: &str, depth: usize, ips: &mut Vec<IpAddr>) {
+        for data in self.records.get(name).into_iter().flatten() {
+            match data {
+                RData::A(a) => ips.push(a.0.into()),
+                RData::AAAA(aaaa) => ips.push(aaaa.0.into()),
+                RData::CNAME(cname) if depth < MAX_CNAME_DEPTH => {
+                    let canonical = to_domain(&cname.0).to_ascii_lowercase();
+                    self.collect_ips(&canonical, depth + 1, ips);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn lookup_srv(&self, name: &str) -> Vec<SRV> {
+        let records = self.records.get(&name.to_ascii_lowercase());
+        records
+            .into_iter()
+            .flatten()
+            .filter_map(|x| match x {
+                RData::SRV(srv) => Some(srv.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn lookup_ptr(&self, ip: IpAddr) -> Vec<String> {
+        let name = to_domain(&Name::from(ip)).to_ascii_lowercase();
+        let records = self.records.get(&name);
+        records
+            .into_iter()
+            .flatten()
+            .filter_map(|x| match x {
+                RData::PTR(ptr) => Some(to_domain(&ptr.0)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Name as a domain, without the trailing dot of a fully qualified name.
+fn to_domain(name: &Name) -> String {
+    let mut domain = name.to_string();
+    if domain.len() > 1 && domain.ends_with('.') {
+        domain.pop();
+    }
+    domain
 }
 
 #[cfg(test)]
@@ -111,6 +416,30 @@ mod tests {
     use super::*;
     use crate::ResolveExt;
 
+    const ZONE: &str = "
+$ORIGIN lab.example.com.
+$TTL 300
+@           IN SOA  ns1 admin 1 3600 600 86400 300
+web1        IN A    10.0.0.1
+web2        IN A    10.0.0.2
+web2        IN AAAA fd00::2
+db1         IN A    10.0.1.1
+www         IN CNAME web1
+_ssh._tcp   IN SRV  20 0  22   db1
+_ssh._tcp   IN SRV  10 10 2201 web1
+_ssh._tcp   IN SRV  10 90 2202 web2
+_ftp._tcp   IN SRV  0  0  0    .
+1.0.0.10.in-addr.arpa. IN PTR web1
+";
+
+    fn zone_resolver() -> Result<(tempfile::TempDir, DnsResolver)> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("lab.zone");
+        std::fs::write(&path, ZONE)?;
+        let resolver = DnsResolver::from_zone_file(path)?.with_reverse(true);
+        Ok((dir, resolver))
+    }
+
     #[rstest]
     #[case("localhost")]
     #[case("127.0.0.1")]
@@ -123,4 +452,94 @@ mod tests {
         assert!(!targets.is_empty());
         Ok(())
     }
+
+    #[rstest]
+    #[case("ssh://localhost")]
+    #[case("cidr://127.0.0.1/32")]
+    #[tokio::test]
+    async fn resolve_ignores_other_kinds(#[case] query: &str) -> eyre::Result<()> {
+        let target = Target::from_str(query)?;
+        let resolver = DnsResolver::try_new()?.with_reverse(true);
+        let targets = resolver.resolve_set(target).await;
+        assert!(targets.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn from_config_overrides_system() -> Result<()> {
+        let config = DnsConfig::builder()
+            .nameservers(vec![SocketAddr::from_str("192.0.2.53:5353")?])
+            .strategy(LookupStrategy::Ipv6)
+            .timeout(Duration::from_millis(250))
+            .attempts(1)
+            .search(vec!["lab.example.com".into()])
+            .cache_size(16)
+            .build();
+        let resolver = DnsResolver::from_config(&config)?;
+        let Backend::Nameservers(dns) = &resolver.backend else {
+            eyre::bail!("expected nameservers");
+        };
+
+        let servers: Vec<_> = dns
+            .config()
+            .name_servers()
+            .iter()
+            .map(|x| (x.socket_addr.to_string(), x.protocol))
+            .collect();
+        assert_eq!(
+            servers,
+            [
+                ("192.0.2.53:5353".to_owned(), Protocol::Udp),
+                ("192.0.2.53:5353".to_owned(), Protocol::Tcp),
+            ]
+        );
+        assert_eq!(dns.config().search(), [Name::from_str("lab.example.com")?]);
+        let opts = dns.options();
+        assert_eq!(opts.ip_strategy, LookupIpStrategy::Ipv6Only);
+        assert_eq!(opts.timeout, Duration::from_millis(250));
+        assert_eq!(opts.attempts, 1);
+        assert_eq!(opts.cache_size, 16);
+        Ok(())
+    }
+
+    #[test]
+    fn from_config_rejects_invalid_search() {
+        let config = DnsConfig::builder()
+            .search(vec!["bad..domain".into()])
+            .build();
+        assert!(DnsResolver::from_config(&config).is_err());
+    }
+
+    #[rustfmt::skip::attributes(case)]
+    #[rstest]
+    #[case("web1.lab.example.com",            &["ip://10.0.0.1"])]
+    #[case("dns://root@WWW.lab.example.com:22", &["ip://root@10.0.0.1:22"])]
+    #[case("web*.lab.example.com",            &["ip://10.0.0.1", "ip://10.0.0.2", "ip://[fd00::2]"])]
+    #[case("*.lab.example.com",               &["ip://10.0.0.1", "ip://10.0.0.2", "ip://10.0.1.1", "ip://[fd00::2]"])]
+    #[case("10.0.0.1",                        &["dns://web1.lab.example.com"])]
+    #[case("missing.lab.example.com",         &[])]
+    #[case("dns+srv://_ftp._tcp.lab.example.com", &[])]
+    #[tokio::test]
+    async fn resolve_zone_works(#[case] query: &str, #[case] should: &[&str]) -> Result<()> {
+        let (_dir, resolver) = zone_resolver()?;
+        let targets = resolver.resolve_set(Target::from_str(query)?).await;
+        let targets: Vec<_> = targets.iter().map(ToString::to_string).collect();
+        assert_eq!(targets, should);
+        Ok(())
+    }
+
+    #[rustfmt::skip::attributes(case)]
+    #[rstest]
+    #[case(false, &["dns://admin@db1.lab.example.com:22", "dns://admin@web1.lab.example.com:2201", "dns://admin@web2.lab.example.com:2202"])]
+    #[case(true,  &["dns://admin@web1.lab.example.com:2201", "dns://admin@web2.lab.example.com:2202"])]
+    #[tokio::test]
+    async fn resolve_srv_works(#[case] preferred: bool, #[case] should: &[&str]) -> Result<()> {
+        let (_dir, resolver) = zone_resolver()?;
+        let resolver = resolver.with_srv_preferred(preferred);
+        let target = Target::from_str("dns+srv://admin@_ssh._tcp.lab.example.com")?;
+        let targets = resolver.resolve_set(target).await;
+        let targets: Vec<_> = targets.iter().map(ToString::to_string).collect();
+        assert_eq!(targets, should);
+        Ok(())
+    }
 }
@@ -0,0 +1,244 @@
+use std::fmt::Write as _;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use astu_types::Target;
+use eyre::Result;
+use eyre::WrapErr;
+use eyre::bail;
+
+/// Directive that reads another inventory file in place of the line.
+const INCLUDE_DIRECTIVE: &str = "@include";
+
+/// Maximum number of parse errors listed before the rest are counted.
+const MAX_LISTED_ERRORS: usize = 10;
+
+/// Reads targets from inventory files.
+///
+/// Each line holds a target URI or short form. Blank lines are ignored, as is
+/// everything from a `#` at the start of a line or after whitespace.
+/// `@include <path>` reads another file in place of the line, relative to
+/// the including file.
+///
+/// Unlike [`Resolve::resolve`](crate::Resolve::resolve), invalid lines are not
+/// dropped: they fail the whole read, naming the file and line of each.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FileResolver;
+
+impl FileResolver {
+    /// Reads the targets in a file and the files it includes, in order.
+    ///
+    /// # Errors
+    ///
+    /// - If a file cannot be read
+    /// - If a line is not a target, or an include is cyclic
+    pub fn resolve_file(&self, path: impl AsRef<Path>) -> Result<Vec<Target>> {
+        let mut reader = Reader::default();
+        reader.read_file(path.as_ref(), None)?;
+        reader.finish()
+    }
+
+    /// Reads the targets in contents that were read from somewhere other than
+    /// a file, such as stdin, and the files it includes. Includes are relative
+    /// to the working directory. The origin names the contents in errors.
+    ///
+    /// # Errors
+    ///
+    /// - If an included file cannot be read
+    /// - If a line is not a target, or an include is cyclic
+    pub fn resolve_contents(&self, contents: &str, origin: &str) -> Result<Vec<Target>> {
+        let mut reader = Reader::default();
+        reader.read_lines(contents, origin, Path::new("."))?;
+        reader.finish()
+    }
+}
+
+#[derive(Debug, Default)]
+struct Reader {
+    targets: Vec<Target>,
+    errors: Vec<String>,
+    /// Files being read, outermost first, to detect cyclic includes.
+    stack: Vec<PathBuf>,
+}
+
+impl Reader {
+    fn read_file(&mut self, path: &Path, included_at: Option<&str>) -> Result<()> {
+        let at = || included_at.map_or_else(String::new, |x| format!("{x}: "));
+        let canonical = path
+            .canonicalize()
+            .wrap_err_with(|| format!("{}failed to read target file {}", at(), path.display()))?;
+        if self.stack.contains(&canonical) {
+            let cycle: Vec<_> = self
+                .stack
+                .iter()
+                .chain([&canonical])
+                .map(|x| x.display().to_string())
+                .collect();
+            bail!("{}cyclic include: {}", at(), cycle.join(" -> "));
+        }
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("{}failed to read target file {}", at(), path.display()))?;
+
+        self.stack.push(canonical);
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        self.read_lines(&contents, &path.display().to_string(), dir)?;
+        self.stack.pop();
+        Ok(())
+    }
+
+    fn read_lines(&mut self, contents: &str, origin: &str, dir: &Path) -> Result<()> {
+        for (i, line) in contents.lines().enumerate() {
+            let at = format!("{origin}:{}", i + 1);
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(include) = line.strip_prefix(INCLUDE_DIRECTIVE) {
+                if !include.starts_with(char::is_whitespace) || include.trim().is_empty() {
+                    self.errors
+                        .push(format!("{at}: expected `{INCLUDE_DIRECTIVE} <path>`"));
+                    continue;
+                }
+                self.read_file(&dir.join(include.trim()), Some(&at))?;
+                continue;
+            }
+            match Target::from_str(line) {
+                Ok(target) => self.targets.push(target),
+                Err(error) => self.errors.push(format!("{at}: {error}")),
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Vec<Target>> {
+        if self.errors.is_empty() {
+            return Ok(self.targets);
+        }
+        let mut message = String::from("invalid target files:");
+        for error in self.errors.iter().take(MAX_LISTED_ERRORS) {
+            let _ = write!(message, "\n  {error}");
+        }
+        if self.errors.len() > MAX_LISTED_ERRORS {
+            let more = self.errors.len() - MAX_LISTED_ERRORS;
+            let _ = write!(message, "\n  ... and {more} more");
+        }
+        bail!(message)
+    }
+}
+
+/// Removes a comment from a line. Comments start with `#` at the start of a
+/// line or after whitespace, since URIs may contain `#` but not whitespace.
+fn strip_comment(line: &str) -> &str {
+    let mut prev = None;
+    for (i, c) in line.char_indices() {
+        if c == '#' && prev.is_none_or(char::is_whitespace) {
+            return &line[..i];
+        }
+        prev = Some(c);
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn write(dir: &Path, name: &str, contents: &str) -> Result<PathBuf> {
+        let path = dir.join(name);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, contents)?;
+        Ok(path)
+    }
+
+    fn strings(targets: &[Target]) -> Vec<String> {
+        targets.iter().map(ToString::to_string).collect()
+    }
+
+    #[rstest]
+    #[case("10.0.0.1", "10.0.0.1")]
+    #[case("10.0.0.1 # db", "10.0.0.1 ")]
+    #[case("# 10.0.0.1", "")]
+    #[case("ssh://host#frag", "ssh://host#frag")]
+    fn strip_comment_works(#[case] line: &str, #[case] should: &str) {
+        assert_eq!(strip_comment(line), should);
+    }
+
+    #[test]
+    fn resolve_file_follows_includes() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        write(
+            dir.path(),
+            "group/db.txt",
+            "# databases\n10.0.0.2\n\n10.0.0.3 # replica\n",
+        )?;
+        let path = write(
+            dir.path(),
+            "hosts.txt",
+            "10.0.0.1\n  @include group/db.txt\nssh://root@web1:22\n",
+        )?;
+
+        let targets = FileResolver.resolve_file(path)?;
+        assert_eq!(
+            strings(&targets),
+            [
+                "ip://10.0.0.1",
+                "ip://10.0.0.2",
+                "ip://10.0.0.3",
+                "ssh://root@web1:22"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn resolve_file_reports_lines() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        write(dir.path(), "other.txt", "bogus://x\n")?;
+        let path = write(
+            dir.path(),
+            "hosts.txt",
+            "10.0.0.1\nnot a target\n@include other.txt\n@include\n",
+        )?;
+
+        let error = FileResolver.resolve_file(&path).map_err(|x| x.to_string());
+        let Err(error) = error else {
+            bail!("expected an error");
+        };
+        let lines: Vec<_> = error.lines().collect();
+        assert_eq!(lines[0], "invalid target files:");
+        assert!(lines[1].starts_with(&format!("  {}:2: ", path.display())));
+        assert!(lines[2].contains("other.txt:1: "));
+        assert!(lines[3].ends_with("hosts.txt:4: expected `@include <path>`"));
+        Ok(())
+    }
+
+    #[test]
+    fn resolve_file_rejects_cycles() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        write(dir.path(), "a.txt", "@include b.txt\n")?;
+        write(dir.path(), "b.txt", "@include a.txt\n")?;
+
+        let error = FileResolver
+            .resolve_file(dir.path().join("a.txt"))
+            .map_err(|x| x.to_string());
+        assert!(error.is_err_and(|x| x.contains("b.txt:1: cyclic include: ")));
+        Ok(())
+    }
+
+    #[test]
+    fn resolve_contents_works() -> Result<()> {
+        let targets = FileResolver.resolve_contents("localhost\n\n::1\n", "<stdin>")?;
+        assert_eq!(strings(&targets), ["dns://localhost", "ip://[::1]"]);
+
+        let error = FileResolver
+            .resolve_contents("x y", "<stdin>")
+            .map_err(|x| x.to_string());
+        assert!(error.is_err_and(|x| x.contains("<stdin>:1: ")));
+        Ok(())
+    }
+}
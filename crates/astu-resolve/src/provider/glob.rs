@@ -0,0 +1,52 @@
+/// Matches a glob of `*` (any characters) and `?` (any one character).
+pub fn matches_glob(pattern: &str, s: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let s: Vec<char> = s.chars().collect();
+    let (mut p, mut i) = (0, 0);
+    // Position of the last `*`, and of the input it was matched up to.
+    let mut star: Option<(usize, usize)> = None;
+    while i < s.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, i));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == s[i] => {
+                p += 1;
+                i += 1;
+            }
+            _ => {
+                let Some((star_p, star_i)) = star else {
+                    return false;
+                };
+                star = Some((star_p, star_i + 1));
+                p = star_p + 1;
+                i = star_i + 1;
+            }
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Whether a string has glob wildcards.
+pub fn is_pattern(s: &str) -> bool {
+    s.contains(['*', '?'])
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("*", "anything", true)]
+    #[case("prod-*", "prod-db-1", true)]
+    #[case("prod-*-1", "prod-db-1", true)]
+    #[case("prod-?", "prod-1", true)]
+    #[case("prod-?", "prod-10", false)]
+    #[case("*.example.com", "example.com", false)]
+    fn matches_glob_works(#[case] pattern: &str, #[case] s: &str, #[case] should: bool) {
+        assert_eq!(matches_glob(pattern, s), should);
+    }
+}
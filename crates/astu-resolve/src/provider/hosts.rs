@@ -0,0 +1,170 @@
+use std::net::IpAddr;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use astu_types::Host;
+use astu_types::Target;
+use astu_types::TargetKind;
+use eyre::Result;
+use eyre::WrapErr;
+use futures::StreamExt;
+use futures::stream::BoxStream;
+
+use crate::Resolve;
+
+/// Path of the system hosts file.
+const SYSTEM_HOSTS_PATH: &str = "/etc/hosts";
+
+/// Resolves names and addresses - both forward and reverse - using a hosts
+/// file, such as `/etc/hosts`.
+///
+/// Forward resolution maps DNS targets to the addresses of every line naming
+/// them, by hostname or alias. Reverse resolution maps IP targets to the
+/// hostname of every line with their address. Lines that fail to parse are
+/// ignored, like the system resolver does.
+#[derive(Debug, Clone)]
+pub struct HostsFileResolver {
+    entries: Arc<Vec<HostsEntry>>,
+    forward: bool,
+    reverse: bool,
+}
+
+impl Resolve for HostsFileResolver {
+    fn resolve_fallible(&self, target: Target) -> BoxStream<'_, Result<Target>> {
+        let fwd = self.forward && target.kind() == TargetKind::Dns;
+        let rev = self.reverse && target.kind() == TargetKind::Ip;
+        let results: Vec<_> = match target.host() {
+            Some(Host::Domain(name)) if fwd => self
+                .entries
+                .iter()
+                .filter(|x| x.names.iter().any(|x| x.eq_ignore_ascii_case(&name)))
+                .map(|x| Target::new_ip(&x.ip, target.port(), target.user().as_deref()))
+                .collect(),
+            Some(Host::Ip(ip)) if rev => self
+                .entries
+                .iter()
+                .filter(|x| x.ip == ip)
+                .map(|x| Target::new_dns(&x.names[0], target.port(), target.user().as_deref()))
+                .collect(),
+            _unsupported => Vec::new(),
+        };
+        futures::stream::iter(results).boxed()
+    }
+}
+
+/// Constructors
+impl HostsFileResolver {
+    /// Creates a resolver from the system hosts file, `/etc/hosts`. A missing
+    /// file resolves nothing. Forward resolution is enabled by default, while
+    /// reverse resolution is disabled.
+    ///
+    /// # Errors
+    ///
+    /// - If the hosts file exists but cannot be read
+    pub fn try_new() -> Result<Self> {
+        let path = Path::new(SYSTEM_HOSTS_PATH);
+        if !path.exists() {
+            return Ok(Self::from_contents(""));
+        }
+        Self::from_path(path)
+    }
+
+    /// Creates a resolver from a hosts file.
+    ///
+    /// # Errors
+    ///
+    /// - If the hosts file cannot be read
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read hosts file {}", path.display()))?;
+        Ok(Self::from_contents(&contents))
+    }
+
+    fn from_contents(contents: &str) -> Self {
+        let entries = contents.lines().filter_map(HostsEntry::parse).collect();
+        Self {
+            entries: Arc::new(entries),
+            forward: true,
+            reverse: false,
+        }
+    }
+}
+
+impl HostsFileResolver {
+    /// Set forward lookup.
+    #[must_use]
+    pub const fn with_forward(mut self, enable: bool) -> Self {
+        self.forward = enable;
+        self
+    }
+
+    /// Set reverse lookup.
+    #[must_use]
+    pub const fn with_reverse(mut self, enable: bool) -> Self {
+        self.reverse = enable;
+        self
+    }
+}
+
+/// An address and its hostname, followed by any aliases.
+#[derive(Debug, Clone)]
+struct HostsEntry {
+    ip: IpAddr,
+    names: Vec<String>,
+}
+
+impl HostsEntry {
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.split_once('#').map_or(line, |(line, _comment)| line);
+        let mut fields = line.split_whitespace();
+        let ip = IpAddr::from_str(fields.next()?).ok()?;
+        let names: Vec<_> = fields.map(ToOwned::to_owned).collect();
+        if names.is_empty() {
+            return None;
+        }
+        Some(Self { ip, names })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::ResolveExt;
+
+    const HOSTS: &str = "
+# Lab machines
+127.0.0.1   localhost
+10.0.0.1    lab1.example.com lab1   # rack 3
+10.0.0.2    lab2.example.com
+fe80::2     lab2.example.com
+not-an-ip   bogus
+10.0.0.3
+";
+
+    #[rustfmt::skip::attributes(case)]
+    #[rstest]
+    #[case("lab1",                   &["ip://10.0.0.1"])]
+    #[case("LAB1.example.com",       &["ip://10.0.0.1"])]
+    #[case("dns://root@lab2.example.com:22", &["ip://root@10.0.0.2:22", "ip://root@[fe80::2]:22"])]
+    #[case("10.0.0.1",               &["dns://lab1.example.com"])]
+    #[case("ip://root@10.0.0.2:22",  &["dns://root@lab2.example.com:22"])]
+    #[case("bogus",                  &[])]
+    #[case("10.0.0.3",               &[])]
+    #[case("ssh://lab1",             &[])]
+    #[tokio::test]
+    async fn resolve_works(#[case] query: &str, #[case] should: &[&str]) -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("hosts");
+        std::fs::write(&path, HOSTS)?;
+        let resolver = HostsFileResolver::from_path(path)?.with_reverse(true);
+
+        let targets = resolver.resolve_set(Target::from_str(query)?).await;
+        let targets: Vec<_> = targets.iter().map(ToString::to_string).collect();
+        assert_eq!(targets, should);
+        Ok(())
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::BTreeSet;
+use std::net::IpAddr;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use astu_types::Host;
+use astu_types::Target;
+use astu_types::TargetKind;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use eyre::Result;
+use eyre::WrapErr;
+use futures::StreamExt;
+use futures::stream::BoxStream;
+use hmac::Hmac;
+use hmac::Mac;
+use sha1::Sha1;
+
+use crate::Resolve;
+
+/// Port that known hosts entries without a port are for.
+const DEFAULT_PORT: u16 = 22;
+
+/// Resolves targets using an SSH known hosts file, such as
+/// `~/.ssh/known_hosts`.
+///
+/// The `known-hosts:` target resolves to every host in the file. Hashed
+/// entries cannot be reversed, so only unhashed hosts are listed.
+///
+/// DNS targets resolve to the addresses listed on the same line as their name,
+/// as `ssh` records with `CheckHostIP`. Names are matched against hashed
+/// entries too, but hashed addresses cannot be recovered. Other lines with the
+/// same host key are not followed, since cloned machines share keys. A name
+/// is matched with the port of its target, and entries for other ports only
+/// match targets with those ports.
+///
+/// `@cert-authority` and `@revoked` entries, and wildcard patterns, are
+/// ignored.
+#[derive(Debug, Clone, Default)]
+pub struct KnownHostsResolver {
+    entries: Arc<Vec<KnownHostsEntry>>,
+}
+
+impl Resolve for KnownHostsResolver {
+    fn resolve_fallible(&self, target: Target) -> BoxStream<'_, Result<Target>> {
+        let results = match (target.kind(), target.host()) {
+            (TargetKind::KnownHosts, _) => self.hosts(),
+            (TargetKind::Dns, Some(Host::Domain(name))) => self.resolve_name(&name, &target),
+            _unsupported => Vec::new(),
+        };
+        futures::stream::iter(results).boxed()
+    }
+}
+
+/// Constructors
+impl KnownHostsResolver {
+    /// Creates a resolver from the user's known hosts file,
+    /// `~/.ssh/known_hosts`. A missing file resolves nothing.
+    ///
+    /// # Errors
+    ///
+    /// - If the known hosts file exists but cannot be read
+    pub fn try_new() -> Result<Self> {
+        let Some(home) = dirs::home_dir() else {
+            return Ok(Self::default());
+        };
+        let path = home.join(".ssh").join("known_hosts");
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Self::from_path(path)
+    }
+
+    /// Creates a resolver from a known hosts file.
+    ///
+    /// # Errors
+    ///
+    /// - If the known hosts file cannot be read
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read known hosts file {}", path.display()))?;
+        let entries = contents
+            .lines()
+            .filter_map(KnownHostsEntry::parse)
+            .collect();
+        Ok(Self {
+            entries: Arc::new(entries),
+        })
+    }
+}
+
+impl KnownHostsResolver {
+    /// Every unhashed host, in file order.
+    fn hosts(&self) -> Vec<Result<Target>> {
+        let mut seen = BTreeSet::new();
+        self.entries
+            .iter()
+            .flat_map(|x| &x.hosts)
+            .filter_map(|x| match x {
+                KnownHost::Plain(host, port) => Some((host, port)),
+                KnownHost::Hashed { .. } => None,
+            })
+            .filter(|&x| seen.insert(x))
+            .map(|(host, &port)| {
+                let port = Some(port).filter(|&x| x != DEFAULT_PORT);
+                match Host::from_str(host) {
+                    Ok(Host::Ip(ip)) => Target::new_ip(&ip, port, None),
+                    _ => Target::new_dns(host, port, None),
+                }
+            })
+            .collect()
+    }
+
+    /// Addresses listed on the lines of a name.
+    fn resolve_name(&self, name: &str, target: &Target) -> Vec<Result<Target>> {
+        let name = name.to_ascii_lowercase();
+        let port = target.port().unwrap_or(DEFAULT_PORT);
+        let mut seen = BTreeSet::new();
+        self.entries
+            .iter()
+            .filter(|x| x.hosts.iter().any(|x| x.matches(&name, port)))
+            .flat_map(|x| &x.hosts)
+            .filter_map(|x| match x {
+                KnownHost::Plain(host, entry_port) if *entry_port == port => {
+                    IpAddr::from_str(host).ok()
+                }
+                _ => None,
+            })
+            .filter(|&x| seen.insert(x))
+            .map(|ip| Target::new_ip(&ip, target.port(), target.user().as_deref()))
+            .collect()
+    }
+}
+
+/// The hosts of a line of a known hosts file.
+#[derive(Debug, Clone)]
+struct KnownHostsEntry {
+    hosts: Vec<KnownHost>,
+}
+
+impl KnownHostsEntry {
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with('@') {
+            return None;
+        }
+        let mut fields = line.split_whitespace();
+        let hosts = fields
+            .next()?
+            .split(',')
+            .filter_map(KnownHost::parse)
+            .collect();
+        // Lines without a key type and key are invalid.
+        fields.next()?;
+        fields.next()?;
+        Some(Self { hosts })
+    }
+}
+
+#[derive(Debug, Clone)]
+enum KnownHost {
+    /// Lowercase host and port.
+    Plain(String, u16),
+    /// HMAC-SHA1 of the host as it is written, keyed by a salt.
+    Hashed { salt: Vec<u8>, hash: Vec<u8> },
+}
+
+impl KnownHost {
+    fn parse(pattern: &str) -> Option<Self> {
+        if let Some(hashed) = pattern.strip_prefix("|1|") {
+            let (salt, hash) = hashed.split_once('|')?;
+            return Some(Self::Hashed {
+                salt: BASE64.decode(salt).ok()?,
+                hash: BASE64.decode(hash).ok()?,
+            });
+        }
+        if pattern.contains(['*', '?', '!']) {
+            return None;
+        }
+        let (host, port) = match pattern.strip_prefix('[') {
+            Some(rest) => {
+                let (host, port) = rest.split_once("]:")?;
+                (host, port.parse().ok()?)
+            }
+            None => (pattern, DEFAULT_PORT),
+        };
+        Some(Self::Plain(host.to_ascii_lowercase(), port))
+    }
+
+    fn matches(&self, name: &str, port: u16) -> bool {
+        match self {
+            Self::Plain(host, host_port) => host == name && *host_port == port,
+            Self::Hashed { salt, hash } => {
+                let written = if port == DEFAULT_PORT {
+                    name.to_owned()
+                } else {
+                    format!("[{name}]:{port}")
+                };
+                let Ok(mut mac) = Hmac::<Sha1>::new_from_slice(salt) else {
+                    return false;
+                };
+                mac.update(written.as_bytes());
+                mac.verify_slice(hash).is_ok()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::ResolveExt;
+
+    /// Hashes a host like `ssh-keygen -H` does.
+    fn hashed(host: &str, salt: &[u8]) -> Result<String> {
+        let mut mac = Hmac::<Sha1>::new_from_slice(salt)?;
+        mac.update(host.as_bytes());
+        let hash = mac.finalize().into_bytes();
+        Ok(format!(
+            "|1|{}|{}",
+            BASE64.encode(salt),
+            BASE64.encode(hash)
+        ))
+    }
+
+    fn known_hosts() -> Result<String> {
+        Ok(format!(
+            "\
+# Comment
+lab1.example.com,10.0.0.1 ssh-ed25519 AAAAkey1
+{} ssh-ed25519 AAAAkey2
+10.0.0.2 ssh-ed25519 AAAAkey2
+[lab3.example.com]:2222,[10.0.0.3]:2222 ssh-ed25519 AAAAkey3
+{} ssh-ed25519 AAAAkey3
+*.example.com ssh-rsa AAAAkey4
+@revoked lab5.example.com ssh-rsa AAAAkey5
+lab1.example.com ssh-rsa AAAAkey6
+10.0.0.6 ssh-rsa AAAAkey6
+{},10.0.0.7 ssh-ed25519 AAAAkey7
+",
+            hashed("lab2.example.com", b"0123456789abcdefghij")?,
+            hashed("[lab4.example.com]:2222", b"jihgfedcba9876543210")?,
+            hashed("lab7.example.com", b"abcdefghij0123456789")?,
+        ))
+    }
+
+    #[rustfmt::skip::attributes(case)]
+    #[rstest]
+    #[case("known_hosts:",                &["dns://lab1.example.com", "dns://lab3.example.com:2222", "ip://10.0.0.1", "ip://10.0.0.2", "ip://10.0.0.3:2222", "ip://10.0.0.6", "ip://10.0.0.7"])]
+    #[case("lab1.example.com",            &["ip://10.0.0.1"])]
+    #[case("dns://root@LAB2.example.com", &[])]
+    #[case("dns://root@LAB7.example.com", &["ip://root@10.0.0.7"])]
+    #[case("lab3.example.com",            &[])]
+    #[case("dns://lab3.example.com:2222", &["ip://10.0.0.3:2222"])]
+    #[case("dns://lab4.example.com:2222", &[])]
+    #[case("lab5.example.com",            &[])]
+    #[case("ssh://lab1.example.com",      &[])]
+    #[tokio::test]
+    async fn resolve_works(#[case] query: &str, #[case] should: &[&str]) -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("known_hosts");
+        std::fs::write(&path, known_hosts()?)?;
+        let resolver = KnownHostsResolver::from_path(path)?;
+
+        let targets = resolver.resolve_set(Target::from_str(query)?).await;
+        let targets: Vec<_> = targets.iter().map(ToString::to_string).collect();
+        assert_eq!(targets, should);
+        Ok(())
+    }
+}
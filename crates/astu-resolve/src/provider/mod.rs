@@ -1,33 +1,58 @@
 mod chain;
 mod cidr;
 mod dns;
+mod file;
+mod glob;
+mod hosts;
+mod known_hosts;
+mod ssh_config;
 
 pub use self::chain::ChainResolver;
 pub use self::cidr::CidrResolver;
+pub use self::dns::DnsConfig;
 pub use self::dns::DnsResolver;
+pub use self::dns::LookupStrategy;
+pub use self::file::FileResolver;
+pub use self::hosts::HostsFileResolver;
+pub use self::known_hosts::KnownHostsResolver;
+pub use self::ssh_config::SshConfigResolver;
 
-/// Create the default chain of forward resolvers.
+/// Create the default chain of forward resolvers, with DNS queried as
+/// configured.
+///
+/// The SSH known hosts file is only a fallback for names that nothing else
+/// resolves, since its addresses may be stale.
 ///
 /// # Errors
 ///
 /// If any of the resolvers in the chain fail to build.
-pub fn forward_chain() -> eyre::Result<ChainResolver> {
+pub fn forward_chain(dns: &DnsConfig) -> eyre::Result<ChainResolver> {
     let chain = ChainResolver::default()
         .with(CidrResolver::default())
-        .with(DnsResolver::try_new()?);
+        .with(HostsFileResolver::try_new()?)
+        .with(DnsResolver::from_config(dns)?)
+        .with(SshConfigResolver::try_new()?)
+        .with_fallback(KnownHostsResolver::try_new()?);
     Ok(chain)
 }
 
-/// Create the default chain of reverse resolvers.
+/// Create the default chain of reverse resolvers, with DNS queried as
+/// configured.
 ///
 /// # Errors
 ///
 /// If any of the resolvers in the chain fail to build.
-pub fn reverse_chain() -> eyre::Result<ChainResolver> {
-    let chain = ChainResolver::default().with(
-        DnsResolver::try_new()?
-            .with_forward(false)
-            .with_reverse(true),
-    );
+pub fn reverse_chain(dns: &DnsConfig) -> eyre::Result<ChainResolver> {
+    let chain = ChainResolver::default()
+        .with(
+            HostsFileResolver::try_new()?
+                .with_forward(false)
+                .with_reverse(true),
+        )
+        .with(
+            DnsResolver::from_config(dns)?
+                .with_forward(false)
+                .with_reverse(true),
+        );
     Ok(chain)
 }
@@ -0,0 +1,557 @@
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use astu_types::Host;
+use astu_types::Target;
+use astu_types::TargetKind;
+use eyre::OptionExt;
+use eyre::Result;
+use eyre::WrapErr;
+use eyre::bail;
+use eyre::eyre;
+use futures::StreamExt;
+use futures::stream::BoxStream;
+
+use crate::Resolve;
+use crate::provider::glob::is_pattern;
+use crate::provider::glob::matches_glob;
+
+/// Maximum nesting of `Include` directives, as in OpenSSH.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Resolves SSH targets to the host, user and port that an OpenSSH client
+/// config sets for them.
+///
+/// `ssh://alias` resolves to `ssh://user@hostname:port`, where each of those
+/// is taken from the config if the target does not set it. A target whose
+/// host has `*` or `?` wildcards, like `ssh://prod-db-*`, expands to every
+/// alias named in a `Host` line that it matches.
+///
+/// Supports `Host` and `Match` blocks, `Include`, and `HostName`, `User` and
+/// `Port`, in which `%h` is the alias. `Match` supports the `all`, `host` and
+/// `originalhost` criteria; blocks with other criteria, including ones it does
+/// not know, never match. Other
+/// keywords are ignored. As in OpenSSH, the first value obtained for each
+/// keyword wins.
+#[derive(Debug, Clone, Default)]
+pub struct SshConfigResolver {
+    directives: Arc<Vec<Directive>>,
+}
+
+impl Resolve for SshConfigResolver {
+    fn resolve_fallible(&self, target: Target) -> BoxStream<'_, Result<Target>> {
+        match target.domain() {
+            Some(alias) if target.kind() == TargetKind::Ssh => {
+                let results: Vec<_> = if is_pattern(alias) {
+                    self.aliases()
+                        .into_iter()
+                        .filter(|x| matches_pattern(alias, x))
+                        .filter_map(|x| self.resolve_alias(&x, &target).transpose())
+                        .collect()
+                } else {
+                    self.resolve_alias(alias, &target)
+                        .transpose()
+                        .into_iter()
+                        .collect()
+                };
+                futures::stream::iter(results).boxed()
+            }
+            _unsupported => futures::stream::empty().boxed(),
+        }
+    }
+}
+
+/// Constructors
+impl SshConfigResolver {
+    /// Creates a resolver from the user's OpenSSH config, `~/.ssh/config`. A
+    /// missing config resolves nothing.
+    ///
+    /// # Errors
+    ///
+    /// - If the config or a file it includes cannot be read or parsed
+    pub fn try_new() -> Result<Self> {
+        let Some(home) = dirs::home_dir() else {
+            return Ok(Self::default());
+        };
+        let path = home.join(".ssh").join("config");
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Self::from_path(path)
+    }
+
+    /// Creates a resolver from an OpenSSH config file. Relative `Include`
+    /// paths are relative to the directory of this file.
+    ///
+    /// # Errors
+    ///
+    /// - If the config or a file it includes cannot be read or parsed
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let mut parser = Parser {
+            dir: path.parent().unwrap_or_else(|| Path::new(".")).to_owned(),
+            directives: Vec::new(),
+        };
+        parser.parse_file(path, &[], 0)?;
+        Ok(Self {
+            directives: Arc::new(parser.directives),
+        })
+    }
+}
+
+impl SshConfigResolver {
+    /// Aliases named in `Host` lines, excluding patterns, in config order.
+    fn aliases(&self) -> Vec<String> {
+        let mut aliases: Vec<String> = Vec::new();
+        let patterns = self
+            .directives
+            .iter()
+            .flat_map(|x| &x.conditions)
+            .filter_map(|x| match x {
+                Condition::Host(patterns) => Some(patterns),
+                Condition::Match(_) => None,
+            })
+            .flatten();
+        for pattern in patterns {
+            if !is_pattern(pattern) && !pattern.starts_with('!') && !aliases.contains(pattern) {
+                aliases.push(pattern.clone());
+            }
+        }
+        aliases
+    }
+
+    /// Applies the config to an alias, if it sets the hostname, user or port.
+    fn resolve_alias(&self, alias: &str, target: &Target) -> Result<Option<Target>> {
+        let alias = alias.to_ascii_lowercase();
+        let mut hostname: Option<String> = None;
+        let mut user: Option<String> = None;
+        let mut port: Option<String> = None;
+        for directive in self.directives.iter() {
+            let host = hostname.as_deref().unwrap_or(&alias);
+            if !directive.conditions.iter().all(|x| x.matches(&alias, host)) {
+                continue;
+            }
+            let value = match directive.keyword.as_str() {
+                "hostname" => &mut hostname,
+                "user" => &mut user,
+                "port" => &mut port,
+                _ => continue,
+            };
+            value.get_or_insert_with(|| expand_tokens(&directive.value, &alias));
+        }
+        if hostname.is_none() && user.is_none() && port.is_none() {
+            return Ok(None);
+        }
+
+        let host = Host::from_str(hostname.as_deref().unwrap_or(&alias))?;
+        let port = match (target.port(), port) {
+            (Some(port), _) => Some(port),
+            (None, Some(port)) => Some(
+                port.parse()
+                    .wrap_err_with(|| format!("invalid port for SSH host {alias}: {port}"))?,
+            ),
+            (None, None) => None,
+        };
+        let target_user = target.user();
+        let user = target_user.as_deref().or(user.as_deref());
+        Target::new_ssh(&host, port, user).map(Some)
+    }
+}
+
+/// A keyword and its value, along with the blocks it is in.
+#[derive(Debug, Clone)]
+struct Directive {
+    /// Conditions of the enclosing blocks, outermost first, which must all
+    /// hold for the directive to apply. Blocks nest via `Include`.
+    conditions: Vec<Condition>,
+    /// Lowercase keyword.
+    keyword: String,
+    value: String,
+}
+
+#[derive(Debug, Clone)]
+enum Condition {
+    /// `Host` patterns.
+    Host(Vec<String>),
+    /// `Match` criteria, which must all hold.
+    Match(Vec<Criterion>),
+}
+
+impl Condition {
+    fn matches(&self, alias: &str, host: &str) -> bool {
+        match self {
+            Self::Host(patterns) => matches_list(patterns, alias),
+            Self::Match(criteria) => criteria.iter().all(|x| x.matches(alias, host)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Criterion {
+    negated: bool,
+    kind: CriterionKind,
+}
+
+#[derive(Debug, Clone)]
+enum CriterionKind {
+    All,
+    /// Patterns matched against the hostname, after `HostName` is applied.
+    Host(Vec<String>),
+    /// Patterns matched against the alias.
+    OriginalHost(Vec<String>),
+    /// A criterion that depends on more than the host, which never matches.
+    Unsupported,
+}
+
+impl Criterion {
+    fn matches(&self, alias: &str, host: &str) -> bool {
+        let matches = match &self.kind {
+            CriterionKind::All => true,
+            CriterionKind::Host(patterns) => matches_list(patterns, host),
+            CriterionKind::OriginalHost(patterns) => matches_list(patterns, alias),
+            CriterionKind::Unsupported => return false,
+        };
+        matches != self.negated
+    }
+}
+
+#[derive(Debug)]
+struct Parser {
+    /// Directory that relative `Include` paths are relative to.
+    dir: PathBuf,
+    directives: Vec<Directive>,
+}
+
+impl Parser {
+    fn parse_file(&mut self, path: &Path, outer: &[Condition], depth: usize) -> Result<()> {
+        if depth > MAX_INCLUDE_DEPTH {
+            bail!("too many nested includes in SSH config {}", path.display());
+        }
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read SSH config {}", path.display()))?;
+
+        let mut block: Option<Condition> = None;
+        for (i, line) in contents.lines().enumerate() {
+            let at = || format!("{}:{}", path.display(), i + 1);
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (keyword, args) = split_line(line).wrap_err_with(at)?;
+            let conditions = || outer.iter().chain(&block).cloned().collect::<Vec<_>>();
+            match keyword.as_str() {
+                "host" => {
+                    let patterns = args.iter().map(|x| x.to_ascii_lowercase()).collect();
+                    block = Some(Condition::Host(patterns));
+                }
+                "match" => {
+                    let criteria = parse_criteria(&args).wrap_err_with(at)?;
+                    block = Some(Condition::Match(criteria));
+                }
+                "include" => {
+                    let conditions = conditions();
+                    for arg in &args {
+                        for include in self.include_paths(arg).wrap_err_with(at)? {
+                            self.parse_file(&include, &conditions, depth + 1)
+                                .wrap_err_with(at)?;
+                        }
+                    }
+                }
+                _ => {
+                    let value = args
+                        .into_iter()
+                        .next()
+                        .ok_or_else(|| eyre!("missing value for {keyword}"))
+                        .wrap_err_with(at)?;
+                    self.directives.push(Directive {
+                        conditions: conditions(),
+                        keyword,
+                        value,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Files matched by an `Include` argument. Wildcards are supported in the
+    /// file name, and files that do not exist are skipped.
+    fn include_paths(&self, arg: &str) -> Result<Vec<PathBuf>> {
+        let path = match arg.strip_prefix("~/") {
+            Some(rest) => dirs::home_dir()
+                .ok_or_eyre("failed to find home directory")?
+                .join(rest),
+            None => self.dir.join(arg),
+        };
+        let name = path
+            .file_name()
+            .and_then(|x| x.to_str())
+            .ok_or_else(|| eyre!("invalid include path: {arg}"))?;
+        if !is_pattern(name) {
+            return Ok(vec![path].into_iter().filter(|x| x.is_file()).collect());
+        }
+
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return Ok(Vec::new());
+        };
+        let mut paths: Vec<_> = entries
+            .filter_map(Result::ok)
+            .map(|x| x.path())
+            .filter(|x| x.is_file())
+            .filter(|x| {
+                x.file_name()
+                    .and_then(|x| x.to_str())
+                    .is_some_and(|x| matches_glob(name, x))
+            })
+            .collect();
+        paths.sort();
+        Ok(paths)
+    }
+}
+
+/// Splits a line into its lowercase keyword and arguments. Keywords are
+/// separated from arguments by whitespace or `=`, and arguments may be
+/// quoted.
+fn split_line(line: &str) -> Result<(String, Vec<String>)> {
+    let end = line
+        .find(|c: char| c.is_whitespace() || c == '=')
+        .unwrap_or(line.len());
+    let (keyword, rest) = line.split_at(end);
+    let rest = rest.trim_start();
+    let rest = rest.strip_prefix('=').unwrap_or(rest);
+
+    let mut args = Vec::new();
+    let mut chars = rest.chars().peekable();
+    loop {
+        while chars.next_if(|x| x.is_whitespace()).is_some() {}
+        let Some(c) = chars.next() else {
+            break;
+        };
+        let mut arg = String::new();
+        if c == '"' {
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some(c) => arg.push(c),
+                    None => bail!("unterminated quote"),
+                }
+            }
+        } else {
+            arg.push(c);
+            while let Some(c) = chars.next_if(|x| !x.is_whitespace()) {
+                arg.push(c);
+            }
+        }
+        args.push(arg);
+    }
+    Ok((keyword.to_ascii_lowercase(), args))
+}
+
+fn parse_criteria(args: &[String]) -> Result<Vec<Criterion>> {
+    let mut criteria = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let lower = arg.to_ascii_lowercase();
+        let (negated, name) = lower
+            .strip_prefix('!')
+            .map_or((false, lower.as_str()), |x| (true, x));
+        let mut patterns = || -> Result<Vec<String>> {
+            let list = args
+                .next()
+                .ok_or_else(|| eyre!("missing argument for Match {name}"))?;
+            Ok(list.split(',').map(str::to_ascii_lowercase).collect())
+        };
+        let kind = match name {
+            "all" => CriterionKind::All,
+            "host" => CriterionKind::Host(patterns()?),
+            "originalhost" => CriterionKind::OriginalHost(patterns()?),
+            "canonical" | "final" => CriterionKind::Unsupported,
+            "exec" | "localnetwork" | "localuser" | "tagged" | "user" | "version" => {
+                patterns()?;
+                CriterionKind::Unsupported
+            }
+            // Criteria of newer OpenSSH versions, like `sessiontype`. Whether
+            // they take an argument is unknown, but the block never matches,
+            // so the rest of the line does not matter.
+            _unknown => {
+                criteria.push(Criterion {
+                    negated,
+                    kind: CriterionKind::Unsupported,
+                });
+                break;
+            }
+        };
+        criteria.push(Criterion { negated, kind });
+    }
+    if criteria.is_empty() {
+        bail!("missing criteria for Match");
+    }
+    Ok(criteria)
+}
+
+/// Whether a host matches a list of patterns: any of the patterns, and none
+/// of those negated with `!`.
+fn matches_list(patterns: &[String], host: &str) -> bool {
+    let mut matched = false;
+    for pattern in patterns {
+        if let Some(pattern) = pattern.strip_prefix('!') {
+            if matches_pattern(pattern, host) {
+                return false;
+            }
+        } else {
+            matched |= matches_pattern(pattern, host);
+        }
+    }
+    matched
+}
+
+fn matches_pattern(pattern: &str, host: &str) -> bool {
+    matches_glob(&pattern.to_ascii_lowercase(), &host.to_ascii_lowercase())
+}
+
+/// Expands the `%h` (alias) and `%%` tokens of a value.
+fn expand_tokens(hostname: &str, alias: &str) -> String {
+    let mut out = String::new();
+    let mut chars = hostname.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('h') => out.push_str(alias),
+            Some(c) => out.push(c),
+            None => out.push('%'),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::ResolveExt;
+
+    const CONFIG: &str = r#"
+# Shared settings
+Host bastion
+    HostName bastion.example.com
+    User jump
+
+Host prod-db-1 prod-db-2
+    User postgres
+
+Host prod-db-1
+    HostName 10.0.1.1
+Host prod-db-2
+    HostName=10.0.1.2
+    Port 2222
+
+Host prod-web-* !prod-web-canary
+    HostName %h.prod.example.com
+
+Match host *.prod.example.com
+    Port 2200
+
+Match host 10.0.1.*
+    Port 5432
+
+Host "quoted"
+    HostName quoted.example.com
+
+Host *
+    User admin
+    ServerAliveInterval 60
+"#;
+
+    fn resolver(config: &str) -> Result<(tempfile::TempDir, SshConfigResolver)> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("config");
+        std::fs::write(&path, config)?;
+        let resolver = SshConfigResolver::from_path(path)?;
+        Ok((dir, resolver))
+    }
+
+    async fn resolve(resolver: &SshConfigResolver, query: &str) -> Result<Vec<String>> {
+        let target = Target::from_str(query)?;
+        let targets = resolver.resolve_set(target).await;
+        Ok(targets.iter().map(ToString::to_string).collect())
+    }
+
+    #[rustfmt::skip::attributes(case)]
+    #[rstest]
+    #[case("ssh://bastion",          &["ssh://jump@bastion.example.com"])]
+    #[case("ssh://root@bastion:23",  &["ssh://root@bastion.example.com:23"])]
+    #[case("ssh://prod-db-1",        &["ssh://postgres@10.0.1.1:5432"])]
+    #[case("ssh://prod-db-2",        &["ssh://postgres@10.0.1.2:2222"])]
+    #[case("ssh://prod-web-1",       &["ssh://admin@prod-web-1.prod.example.com:2200"])]
+    #[case("ssh://prod-web-canary",  &["ssh://admin@prod-web-canary"])]
+    #[case("ssh://quoted",           &["ssh://admin@quoted.example.com"])]
+    #[case("ssh://prod-db-*",        &["ssh://postgres@10.0.1.1:5432", "ssh://postgres@10.0.1.2:2222"])]
+    #[case("ssh://prod-*-2",         &["ssh://postgres@10.0.1.2:2222"])]
+    #[case("ssh://staging-*",        &[])]
+    #[case("dns://bastion",          &[])]
+    #[tokio::test]
+    async fn resolve_works(#[case] query: &str, #[case] should: &[&str]) -> Result<()> {
+        let (_dir, resolver) = resolver(CONFIG)?;
+        assert_eq!(resolve(&resolver, query).await?, should);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn resolve_follows_includes() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::create_dir(dir.path().join("conf.d"))?;
+        std::fs::write(
+            dir.path().join("conf.d/10-db.conf"),
+            "HostName db.example.com\nHost web\nHostName web.example.com\n",
+        )?;
+        std::fs::write(dir.path().join("conf.d/20-user.conf"), "User deploy\n")?;
+        std::fs::write(
+            dir.path().join("config"),
+            "Host db\n    Include conf.d/*.conf missing.conf\n",
+        )?;
+
+        let resolver = SshConfigResolver::from_path(dir.path().join("config"))?;
+        assert_eq!(
+            resolve(&resolver, "ssh://db").await?,
+            ["ssh://deploy@db.example.com"]
+        );
+        // A block in an included file only applies within the including one.
+        assert!(resolve(&resolver, "ssh://web").await?.is_empty());
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("Match sessiontype shell")]
+    #[case("Match command uptime host web")]
+    #[case("Match !bogus")]
+    #[case("Match host web bogus")]
+    #[tokio::test]
+    async fn resolve_ignores_unknown_match_criteria(#[case] line: &str) -> Result<()> {
+        let config = format!(
+            "{line}\n    HostName other.example.com\nHost web\n    HostName web.example.com\n"
+        );
+        let (_dir, resolver) = resolver(&config)?;
+        assert_eq!(
+            resolve(&resolver, "ssh://web").await?,
+            ["ssh://web.example.com"]
+        );
+        Ok(())
+    }
+
+    #[rustfmt::skip::attributes(case)]
+    #[rstest]
+    #[case("Match exec\n",           "config:1: missing argument for Match exec")]
+    #[case("Host a\n  HostName\n",   "config:2: missing value for hostname")]
+    #[case("Host \"a\n",             "config:1: unterminated quote")]
+    fn from_path_rejects_invalid(#[case] config: &str, #[case] should: &str) {
+        let error = resolver(config).map_err(|x| format!("{x:#}"));
+        assert!(error.is_err_and(|x| x.contains(should)), "{should}");
+    }
+}